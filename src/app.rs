@@ -1,132 +1,554 @@
 //! Kmouse application implementation
 
 use eframe::{
-    egui::{CentralPanel, Context, ViewportBuilder},
+    egui::{
+        pos2, vec2, CentralPanel, Context, Event, Modifiers, RawInput, Rect, ViewportBuilder,
+        ViewportCommand,
+    },
     App, NativeOptions,
 };
-use std::sync::{Arc, Mutex};
-
-use crate::config::AppConfig;
-use crate::models::cell::FocusedCell;
-use crate::ui::{self, grid};
-use crate::{
-    input::keyboard::{self, CTX_CELL},
-    models::margin::Margin as KMargin,
-};
+use std::sync::{Arc, Mutex, TryLockError};
+use std::time::Duration;
+
+use crate::config::{AppConfig, ConfigFile, SharedConfig};
+use crate::error::Result;
+use crate::input::ipc;
+use crate::input::keyboard::{self, CTX_CELL};
+use crate::input::mouse::{self, Pointer, Scroller};
+use crate::input::overlay::{Overlay, PointerRequest};
+use crate::models::cell::{CellPlural, ScrollState};
+use crate::models::monitor::Monitor;
+use crate::models::selection::Selection;
+#[cfg(feature = "wayland-overlay")]
+use crate::system::layer_shell::LayerShellOverlay;
+use crate::system::x11::KeyboardGrab;
+use crate::system::{reload, wayland};
+use crate::ui::grid::{self, GridFrame};
+use crate::ui::hints::{self, HintFrame};
+use crate::ui::{self, banner, RenderSettings};
+
+/// How often grabbed keys are polled while the overlay is visible
+const GRAB_POLL_INTERVAL: Duration = Duration::from_millis(16);
 
 /// Main application state
 pub struct KmouseApp {
-    /// Grid cells
+    /// Grid cells, labelled on first draw
     cells: Vec<crate::models::cell::CellPlural>,
 
-    /// Currently focused cell
-    focused_cell: Arc<Mutex<FocusedCell>>,
+    /// Hint labels, one per hint target
+    hint_labels: Vec<CellPlural>,
+
+    /// Overlay state shared with the input sources
+    overlay: Overlay,
 
-    /// Visibility state
-    is_visible: Arc<Mutex<bool>>,
+    /// Pointer backend, reconnected with backoff when unavailable
+    pointer: Pointer,
 
-    /// Initialization state
-    initiated: Arc<Mutex<bool>>,
+    /// Scroll step and acceleration tracking
+    scroller: Scroller,
+
+    /// Exit after a single selection
+    once: bool,
 
     /// Configuration
     config: AppConfig,
+
+    /// Configuration shared with the input threads and rebuilt on reload
+    shared_config: SharedConfig,
+
+    /// Screen area the overlay window was last placed on, and the scale its
+    /// size was computed for
+    placement: Option<(Monitor, f32)>,
+
+    /// Last keyboard grab failure, logged once until it changes
+    grab_error: Option<String>,
+
+    /// Whether the overlay is a layer-shell surface, which takes the
+    /// keyboard itself and covers a single output
+    layer_shell: bool,
+
+    /// Size of the layer-shell overlay in pixels, which clicks are relative
+    /// to instead of the screen
+    overlay_extent: Option<(i32, i32)>,
 }
 
 impl KmouseApp {
     /// Create a new instance of the application
-    pub fn new(config: AppConfig) -> Self {
+    pub fn new(config: AppConfig, overrides: ConfigFile, once: bool) -> Self {
+        let shared_config = SharedConfig::new(config.clone(), overrides);
+
         Self {
-            cells: grid::generate_letter_combinations(),
-            focused_cell: Arc::new(Mutex::new(FocusedCell::new())),
-            is_visible: Arc::new(Mutex::new(true)),
-            initiated: Arc::new(Mutex::new(false)),
+            cells: Vec::new(),
+            hint_labels: Vec::new(),
+            overlay: Overlay::new(),
+            pointer: Pointer::new(
+                config.pointer_backend,
+                (config.screen_width, config.screen_height),
+            ),
+            scroller: Scroller::new(config.scroll_step, config.scroll_acceleration),
+            once,
             config,
+            shared_config,
+            placement: None,
+            grab_error: None,
+            layer_shell: false,
+            overlay_extent: None,
         }
     }
 
     /// Run the application
-    pub fn run(config: AppConfig) -> eframe::Result<()> {
-        let app = Self::new(config.clone());
+    ///
+    /// With `once`, the overlay is shown without the global key listener
+    /// and the application exits after a single selection.
+    pub fn run(config: AppConfig, overrides: ConfigFile, once: bool) -> Result<()> {
+        let app = Self::new(config.clone(), overrides, once);
+        release_keyboard_on_panic(Arc::clone(&app.overlay.keyboard));
+
+        // The overlay starts visible, so a single selection needs no listeners
+        let mut socket = None;
+        if !once {
+            // Global keys can't be read on Wayland; the compositor binds
+            // `kmouse msg toggle` instead
+            if wayland::is_wayland_session() {
+                eprintln!("Wayland session: bind `kmouse msg toggle` in the compositor");
+            } else if let Err(e) =
+                keyboard::start_keyboard_listener(app.overlay.clone(), app.shared_config.clone())
+            {
+                eprintln!("Failed to start keyboard listener: {}", e);
+            }
 
-        // Set up keyboard listener
-        let visible_clone = Arc::clone(&app.is_visible);
-        let initiated_clone = Arc::clone(&app.initiated);
-        let focused_cell_clone = Arc::clone(&app.focused_cell);
+            // Set up the control socket
+            match ipc::start_ipc_listener(app.overlay.clone(), app.shared_config.clone()) {
+                Ok(file) => socket = Some(file),
+                Err(e) => eprintln!("Failed to start control socket: {}", e),
+            }
 
-        if let Err(e) = keyboard::start_keyboard_listener(
-            visible_clone,
-            initiated_clone,
-            focused_cell_clone,
-            config.toggle_key,
-        ) {
-            eprintln!("Failed to start keyboard listener: {}", e);
+            // Reload the configuration when the file changes or on SIGHUP
+            if let Err(e) = reload::start_config_watcher(app.shared_config.clone()) {
+                eprintln!("Failed to watch the config file: {}", e);
+            }
+            if let Err(e) = reload::start_signal_handler(app.shared_config.clone()) {
+                eprintln!("Failed to handle SIGHUP: {}", e);
+            }
+        }
+
+        // wlroots based compositors get a layer-shell overlay; others, or
+        // compositors without the protocol, a regular window
+        #[cfg(feature = "wayland-overlay")]
+        if wayland::is_wayland_session() {
+            match LayerShellOverlay::new() {
+                Ok(overlay) => {
+                    let result = app.run_on_layer_shell(overlay);
+                    drop(socket);
+                    return result;
+                }
+                Err(e) => eprintln!("No layer shell, using a regular window: {}", e),
+            }
         }
 
         // Set up eframe options
+        let viewport = ViewportBuilder::default()
+            .with_mouse_passthrough(true)
+            .with_transparent(true)
+            .with_titlebar_shown(false)
+            .with_always_on_top()
+            .with_decorations(false)
+            .with_app_id("kmouse");
+
+        // Cover the work area; the first frame corrects this for the scale
+        let work_area = config.work_area;
+        let viewport = viewport
+            .with_position([work_area.x as f32, work_area.y as f32])
+            .with_inner_size([work_area.width as f32, work_area.height as f32]);
+
         let native_options = NativeOptions {
-            viewport: ViewportBuilder::default()
-                .with_mouse_passthrough(true)
-                .with_fullscreen(true)
-                .with_transparent(true)
-                .with_titlebar_shown(false)
-                .with_always_on_top()
-                .with_decorations(false),
+            viewport,
             ..Default::default()
         };
 
-        // Run the application
-        eframe::run_native("Kmouse", native_options, Box::new(|_cc| Ok(Box::new(app))))
+        // Run the application, then remove the control socket
+        let result =
+            eframe::run_native("Kmouse", native_options, Box::new(|_cc| Ok(Box::new(app))));
+        drop(socket);
+        Ok(result?)
     }
 }
 
+impl KmouseApp {
+    /// Switch to a reloaded configuration
+    fn apply_config(&mut self, config: AppConfig) {
+        // Relabel when the labels or the grid layout changed
+        if config.alphabets != self.config.alphabets
+            || config.cell_size != self.config.cell_size
+            || config.monitors != self.config.monitors
+        {
+            self.cells.clear();
+            self.hint_labels.clear();
+        }
+
+        self.scroller = Scroller::new(config.scroll_step, config.scroll_acceleration);
+        self.config = config;
+        self.pointer
+            .configure(self.config.pointer_backend, self.pointer_extent());
+    }
+
+    /// Get the size of the area pointer coordinates are relative to
+    fn pointer_extent(&self) -> (i32, i32) {
+        self.overlay_extent
+            .unwrap_or((self.config.screen_width, self.config.screen_height))
+    }
+
+    /// Place the window on the work area, or on the part of the selected
+    /// monitor inside it, and return where the window is on the screen
+    fn place_window(&mut self, ctx: &Context, monitor: Option<usize>) -> (i32, i32) {
+        let work_area = self.config.work_area;
+        let target = match monitor {
+            Some(index) if self.config.monitors.len() > 1 => self
+                .config
+                .monitors
+                .get(index)
+                .and_then(|monitor| monitor.intersect(&work_area))
+                .unwrap_or(work_area),
+            _ => work_area,
+        };
+
+        // Resize when the window moved to a monitor with another scale too
+        let pixels_per_point = ctx.pixels_per_point();
+        if self.placement != Some((target, pixels_per_point)) {
+            // Viewport commands take points, the work area is in pixels
+            ctx.send_viewport_cmd(ViewportCommand::OuterPosition(pos2(
+                target.x as f32 / pixels_per_point,
+                target.y as f32 / pixels_per_point,
+            )));
+            ctx.send_viewport_cmd(ViewportCommand::InnerSize(vec2(
+                target.width as f32 / pixels_per_point,
+                target.height as f32 / pixels_per_point,
+            )));
+            self.placement = Some((target, pixels_per_point));
+        }
+
+        // Tiling window managers and Wayland compositors may put the window
+        // elsewhere, so clicks are mapped from where it actually is
+        let actual = ctx.input(|i| i.viewport().inner_rect.or(i.viewport().outer_rect));
+        match actual {
+            Some(rect) => (
+                (rect.min.x * pixels_per_point).round() as i32,
+                (rect.min.y * pixels_per_point).round() as i32,
+            ),
+            None => (target.x, target.y),
+        }
+    }
+
+    /// Reconnect a lost pointer backend while the overlay is visible
+    ///
+    /// Until it is back only the selections that click fail; window hints
+    /// that activate and exit_key keep working.
+    fn update_pointer(&mut self, ctx: &Context) {
+        if !self.overlay.is_visible() {
+            return;
+        }
+
+        // The layer-shell overlay covers one output, which clicks map to
+        if self.layer_shell {
+            let pixels_per_point = ctx.pixels_per_point();
+            let size = ctx.screen_rect().size() * pixels_per_point;
+            self.overlay_extent = Some((size.x.round() as i32, size.y.round() as i32));
+            self.pointer
+                .configure(self.config.pointer_backend, self.pointer_extent());
+        }
+
+        if let Err(e) = self.pointer.reconnect() {
+            let delay = self.pointer.retry_in().unwrap_or_default();
+            banner::report_error(
+                &format!(
+                    "Mouse control is unavailable, retrying in {}s",
+                    delay.as_secs() + 1
+                ),
+                e,
+            );
+        }
+        if let Some(delay) = self.pointer.retry_in() {
+            ctx.request_repaint_after(delay);
+        }
+    }
+
+    /// Perform the mouse actions the input threads asked for
+    fn run_pointer_requests(&mut self) {
+        for request in self.overlay.take_pointer_requests() {
+            match request {
+                PointerRequest::ReleaseButton => {
+                    if let Err(e) = mouse::release_button(&mut self.pointer) {
+                        banner::report_error("Failed to release the mouse button", e);
+                    }
+                }
+                PointerRequest::ActAt(x, y, action, reply) => {
+                    // The requester reports the error; it may have stopped waiting
+                    let result = mouse::move_cursor_to(x, y, action, &mut self.pointer);
+                    let _ = reply.send(result);
+                }
+            }
+        }
+    }
+
+    /// Grab the keyboard while the overlay is visible, in exclusive mode
+    fn update_keyboard_grab(&mut self, ctx: &Context) {
+        let is_visible = self.overlay.is_visible();
+        let mut keyboard = self.overlay.keyboard.lock().unwrap();
+        if !self.config.grab_keyboard || self.layer_shell {
+            *keyboard = None;
+            return;
+        }
+
+        if keyboard.is_none() {
+            match KeyboardGrab::new() {
+                Ok(grab) => *keyboard = Some(grab),
+                Err(e) => {
+                    eprintln!(
+                        "Failed to set up the keyboard grab, typing passes through: {}",
+                        e
+                    );
+                    self.config.grab_keyboard = false;
+                    return;
+                }
+            }
+        }
+        let Some(grab) = keyboard.as_mut() else {
+            return;
+        };
+
+        if !is_visible {
+            grab.ungrab();
+            return;
+        }
+
+        // Another client may hold a grab, e.g. the window manager while the
+        // toggle chord is held; try again next frame
+        match grab.grab() {
+            Ok(_) => self.grab_error = None,
+            Err(e) => {
+                let message = e.to_string();
+                if self.grab_error.as_ref() != Some(&message) {
+                    eprintln!("Failed to grab the keyboard: {}", message);
+                    self.grab_error = Some(message);
+                }
+            }
+        }
+
+        // Grabbed keys bypass winit, so keep polling for them
+        ctx.request_repaint_after(GRAB_POLL_INTERVAL);
+    }
+}
+
+/// Release the keyboard grab when any thread panics
+///
+/// If the grab is locked by the panicking thread, abort instead: the X
+/// server releases the grab when the connection closes.
+fn release_keyboard_on_panic(keyboard: Arc<Mutex<Option<KeyboardGrab>>>) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let released = match keyboard.try_lock() {
+            Ok(mut keyboard) => {
+                keyboard.take();
+                true
+            }
+            Err(TryLockError::Poisoned(poisoned)) => {
+                poisoned.into_inner().take();
+                true
+            }
+            Err(TryLockError::WouldBlock) => false,
+        };
+
+        default_hook(info);
+        if !released {
+            std::process::abort();
+        }
+    }));
+}
+
 impl App for KmouseApp {
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
+        self.frame(ctx);
+    }
+
+    fn raw_input_hook(&mut self, _ctx: &Context, raw_input: &mut RawInput) {
+        self.feed_grabbed_keys(raw_input);
+    }
+}
+
+impl KmouseApp {
+    /// Draw every frame on a layer-shell surface until the overlay closes
+    #[cfg(feature = "wayland-overlay")]
+    fn run_on_layer_shell(mut self, overlay: LayerShellOverlay) -> Result<()> {
+        self.layer_shell = true;
+        overlay.run(|ctx| self.frame(ctx))
+    }
+
+    /// Draw a frame of the overlay, in a window or on a layer surface
+    fn frame(&mut self, ctx: &Context) {
         // Store context for global access
         let _ = CTX_CELL.set(Arc::new(ctx.clone()));
 
-        // Determine margin based on application state
-        let has_started = *self.initiated.lock().unwrap();
-        let margin = if has_started {
-            self.config.coordinates_margin = KMargin {
-                top: self.config.base_margin.top,
-                left: self.config.base_margin.left,
-                right: self.config.base_margin.right,
-                bottom: self.config.base_margin.bottom,
-            };
-            KMargin::default().to_egui()
-        } else {
-            self.config.coordinates_margin = KMargin::default();
-            eframe::egui::Margin {
-                top: self.config.frame_margin.top as i8,
-                left: self.config.frame_margin.left as i8,
-                right: self.config.frame_margin.right as i8,
-                bottom: self.config.frame_margin.bottom as i8,
-            }
-        };
+        // Pick up a reloaded configuration
+        if let Some(config) = self.shared_config.take_changed() {
+            self.apply_config(config);
+        }
 
-        // Create frame
-        let transparent_frame = ui::create_transparent_frame(margin, self.config.ui_transparency);
+        self.update_keyboard_grab(ctx);
+        self.update_pointer(ctx);
+        self.run_pointer_requests();
+
+        // Cover the work area, or the selected monitor's part of it
+        let monitor = self.overlay.focused_cell.lock().unwrap().monitor;
+        let origin = self.place_window(ctx, monitor);
+        let spans_monitors = self.config.monitors.len() > 1;
 
-        // Ensure fullscreen
-        ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Fullscreen(true));
+        // Create frame
+        let settings = RenderSettings::from_config(&self.config);
+        let transparent_frame = ui::create_transparent_frame(&settings.theme);
 
         // Draw the UI
         CentralPanel::default()
             .frame(transparent_frame)
             .show(ctx, |ui| {
-                let mut focused_cell = self.focused_cell.lock().unwrap();
-                let mut is_visible = self.is_visible.lock().unwrap();
-
-                grid::draw_grid(
-                    ctx,
-                    ui,
-                    &self.cells,
-                    &mut focused_cell,
-                    &mut is_visible,
-                    &self.config.coordinates_margin,
-                    self.config.ui_transparency,
-                    self.config.exit_key,
-                );
+                let mut focused_cell = self.overlay.focused_cell.lock().unwrap();
+                let mut is_visible = self.overlay.is_visible.lock().unwrap();
+                let mut drag = self.overlay.drag.lock().unwrap();
+                let mut scroll = self.overlay.scroll.lock().unwrap();
+                let move_only = *self.overlay.move_only.lock().unwrap();
+                let mut hint_targets = self.overlay.hints.lock().unwrap();
+
+                // A single selection closes the application, as does
+                // exit_key before anything was typed
+                if self.once {
+                    let idle = focused_cell.typed.is_empty()
+                        && focused_cell.pending.is_none()
+                        && !drag.is_active()
+                        && !scroll.is_active();
+                    if idle && ctx.input(|i| i.key_pressed(self.config.exit_key)) {
+                        *is_visible = false;
+                    }
+                    if !*is_visible {
+                        ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Close);
+                        return;
+                    }
+                }
+
+                // Scroll under the cursor once it has been placed
+                if *scroll == ScrollState::Scrolling {
+                    grid::draw_scroll_mode(
+                        ctx,
+                        ui,
+                        ui.max_rect(),
+                        &mut self.pointer,
+                        &mut scroll,
+                        &mut self.scroller,
+                        &mut is_visible,
+                        &settings,
+                        self.config.exit_key,
+                    );
+                    return;
+                }
+
+                // Label the elements of the active window in hint mode
+                if !hint_targets.is_empty() {
+                    let hints = HintFrame {
+                        origin,
+                        settings: &settings,
+                        config: &self.config,
+                        targets: &mut hint_targets,
+                        labels: &mut self.hint_labels,
+                    };
+                    let selection = Selection {
+                        focused_cell: &mut focused_cell,
+                        drag: &mut drag,
+                        scroll: &mut scroll,
+                        move_only,
+                    };
+                    hints::draw_hints(
+                        ctx,
+                        ui,
+                        &mut self.pointer,
+                        hints,
+                        selection,
+                        &mut is_visible,
+                    );
+                    return;
+                }
+
+                // Pick a monitor first when there is more than one
+                if spans_monitors && !focused_cell.has_monitor() {
+                    let pixels_per_point = ctx.pixels_per_point();
+                    let monitors: Vec<Rect> = self
+                        .config
+                        .monitors
+                        .iter()
+                        .map(|m| {
+                            m.to_egui_rect(origin, pixels_per_point)
+                                .intersect(ui.max_rect())
+                        })
+                        .collect();
+
+                    grid::draw_monitor_picker(ctx, ui, &monitors, &mut focused_cell, &settings);
+                    return;
+                }
+
+                // The window covers the selected monitor, so the grid fills it
+                let grid = GridFrame {
+                    area: ui.max_rect(),
+                    origin,
+                    settings: &settings,
+                    config: &self.config,
+                    cells: &mut self.cells,
+                };
+                let selection = Selection {
+                    focused_cell: &mut focused_cell,
+                    drag: &mut drag,
+                    scroll: &mut scroll,
+                    move_only,
+                };
+                grid::draw_grid(ctx, ui, &mut self.pointer, grid, selection, &mut is_visible);
             });
+
+        banner::draw_error_banner(ctx, ctx.screen_rect());
+
+        // Selections hide the overlay from the UI; let go of the keyboard now
+        if !self.overlay.is_visible() {
+            self.overlay.release_keyboard();
+        }
+    }
+
+    /// Feed the keys read from the keyboard grab to egui
+    fn feed_grabbed_keys(&mut self, raw_input: &mut RawInput) {
+        let mut keyboard = self.overlay.keyboard.lock().unwrap();
+        let Some(grab) = keyboard.as_mut().filter(|grab| grab.is_grabbed()) else {
+            return;
+        };
+
+        // Feed the grabbed keys to egui as if they came from the window
+        let keys = match grab.poll_keys() {
+            Ok(keys) => keys,
+            Err(e) => {
+                eprintln!("Failed to read grabbed keys: {}", e);
+                return;
+            }
+        };
+        for grabbed in keys {
+            raw_input.modifiers = Modifiers {
+                alt: grabbed.alt,
+                ctrl: grabbed.ctrl,
+                shift: grabbed.shift,
+                command: grabbed.ctrl,
+                ..Modifiers::default()
+            };
+            if let Some(key) = keyboard::key_from_keysym(grabbed.keysym) {
+                raw_input.events.push(Event::Key {
+                    key,
+                    physical_key: None,
+                    pressed: grabbed.pressed,
+                    repeat: false,
+                    modifiers: raw_input.modifiers,
+                });
+            }
+        }
     }
 }
@@ -0,0 +1,47 @@
+//! Command-line interface
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::config::ConfigFile;
+
+/// Kmouse: control the mouse pointer from the keyboard
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Show the overlay immediately, perform a single selection and exit
+    #[arg(long)]
+    pub once: bool,
+
+    /// Settings applied on top of the config file
+    #[command(flatten)]
+    pub overrides: ConfigFile,
+}
+
+/// Kmouse subcommands
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the overlay (the default)
+    Run,
+    /// Print the effective configuration and exit
+    PrintConfig,
+    /// Validate a config file and exit
+    CheckConfig {
+        /// Config file to check, the user config file by default
+        path: Option<PathBuf>,
+    },
+    /// Send a command to the running instance
+    ///
+    /// Commands: show [MODE], hide, toggle [MODE], mode MODE,
+    /// click-at X Y [ACTION] and reload-config. Modes are grid, move, drag,
+    /// scroll, hints and windows.
+    Msg {
+        /// The command and its arguments
+        #[arg(required = true, num_args = 1..)]
+        command: Vec<String>,
+    },
+}
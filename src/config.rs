@@ -1,8 +1,21 @@
 //! Configuration for the Kmouse application
 
-use crate::error::Result;
-use crate::models::margin::Margin;
-use crate::system::x11;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use eframe::egui::Color32;
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+use crate::input::hotkey::Hotkey;
+use crate::input::keyboard::CTX_CELL;
+use crate::input::mouse::MouseAction;
+use crate::input::pointer::PointerBackendKind;
+use crate::models::hint::WindowAction;
+use crate::models::label::{Alphabets, LabelOrder};
+use crate::models::monitor::Monitor;
+use crate::system::{wayland, x11};
+use crate::ui::theme::{self, ThemePreset};
 
 /// Application configuration
 #[derive(Debug, Clone)]
@@ -11,52 +24,378 @@ pub struct AppConfig {
     pub screen_width: i32,
     pub screen_height: i32,
 
-    /// Work area margins
-    pub frame_margin: Margin,
-    pub coordinates_margin: Margin,
-    pub base_margin: Margin,
+    /// Active monitors, ordered left to right
+    pub monitors: Vec<Monitor>,
+
+    /// Screen area left free by docks and panels, which the overlay covers
+    pub work_area: Monitor,
 
     /// UI settings
     pub cell_size: f32,
     pub font_scale: f32,
+    pub micro_font_scale: f32,
     pub ui_transparency: u8,
 
+    /// Color preset, and single colors overriding it
+    pub theme: ThemePreset,
+    pub tint_color: Option<Color32>,
+    pub grid_line_color: Option<Color32>,
+    pub label_color: Option<Color32>,
+    pub badge_color: Option<Color32>,
+    pub badge_text_color: Option<Color32>,
+    pub prefix_color: Option<Color32>,
+
+    /// Grid and micro grid label alphabets
+    pub alphabets: Alphabets,
+
+    /// Zoom into micro grid cells instead of clicking right away
+    pub recursive_micro_grid: bool,
+
+    /// Place the pointer and wait for Enter or Space before clicking
+    pub confirm_click: bool,
+
+    /// Grab the keyboard while the overlay is visible, so typed labels do
+    /// not reach the application underneath
+    pub grab_keyboard: bool,
+
+    /// Action performed when no modifier is held on the final keypress
+    pub click_action: MouseAction,
+
+    /// What picking a window in window hint mode does
+    pub window_action: WindowAction,
+
+    /// How mouse events are sent
+    pub pointer_backend: PointerBackendKind,
+
+    /// Scroll mode settings, in wheel notches
+    pub scroll_step: i32,
+    pub scroll_acceleration: f32,
+
     /// Key bindings
-    pub toggle_key: rdev::Key,
+    pub toggle_key: Hotkey,
+    /// Unset by default, since no key is free on every keyboard layout
+    pub move_toggle_key: Option<Hotkey>,
+    pub hint_toggle_key: Hotkey,
+    pub window_toggle_key: Hotkey,
     pub exit_key: eframe::egui::Key,
+    pub drag_key: eframe::egui::Key,
+    pub scroll_key: eframe::egui::Key,
+}
+
+/// User settings read from the config file or the command line
+///
+/// Every field is optional; missing fields keep their default value.
+#[derive(Debug, Clone, Default, Deserialize, clap::Args)]
+#[serde(deny_unknown_fields)]
+#[command(next_help_heading = "Config overrides")]
+pub struct ConfigFile {
+    #[arg(long)]
+    cell_size: Option<f32>,
+    #[arg(long)]
+    font_scale: Option<f32>,
+    #[arg(long)]
+    micro_font_scale: Option<f32>,
+    #[arg(long = "transparency")]
+    ui_transparency: Option<u8>,
+    #[arg(long)]
+    theme: Option<String>,
+    #[arg(long)]
+    tint_color: Option<String>,
+    #[arg(long)]
+    grid_line_color: Option<String>,
+    #[arg(long)]
+    label_color: Option<String>,
+    #[arg(long)]
+    badge_color: Option<String>,
+    #[arg(long)]
+    badge_text_color: Option<String>,
+    #[arg(long)]
+    prefix_color: Option<String>,
+    #[arg(long)]
+    keyboard_layout: Option<String>,
+    #[arg(long)]
+    hint_alphabet: Option<String>,
+    #[arg(long)]
+    micro_alphabet: Option<String>,
+    #[arg(long)]
+    label_order: Option<String>,
+    #[arg(long)]
+    recursive_micro_grid: Option<bool>,
+    #[arg(long)]
+    confirm_click: Option<bool>,
+    #[arg(long)]
+    grab_keyboard: Option<bool>,
+    #[arg(long)]
+    click_action: Option<String>,
+    #[arg(long)]
+    window_action: Option<String>,
+    #[arg(long)]
+    pointer_backend: Option<String>,
+    #[arg(long)]
+    scroll_step: Option<i32>,
+    #[arg(long)]
+    scroll_acceleration: Option<f32>,
+    #[arg(long)]
+    toggle_key: Option<String>,
+    #[arg(long)]
+    move_toggle_key: Option<String>,
+    #[arg(long)]
+    hint_toggle_key: Option<String>,
+    #[arg(long)]
+    window_toggle_key: Option<String>,
+    #[arg(long)]
+    exit_key: Option<String>,
+    #[arg(long)]
+    drag_key: Option<String>,
+    #[arg(long)]
+    scroll_key: Option<String>,
 }
 
 impl AppConfig {
-    /// Load configuration from the system
+    /// Load configuration from the system and the user config file
     pub fn load() -> Result<Self> {
-        // Get work area from X11
-        let (work_x, work_y, work_width, work_height) = x11::get_work_area()?;
-        let screen_width = 1920;
-        let screen_height = 1080;
-
-        // Calculate margins
-        let margin_top = work_y;
-        let margin_bottom = screen_height - (work_y + work_height);
-        let margin_left = work_x;
-        let margin_right = screen_width - (work_x + work_width);
-        let margin = Margin {
-            top: margin_top as i32,
-            left: margin_left as i32,
-            right: margin_right as i32,
-            bottom: margin_bottom as i32,
+        // The Wayland overlay covers the first output, so that is the screen;
+        // otherwise get screen size and work area from X11
+        if wayland::is_wayland_session() {
+            if let Ok((width, height)) = wayland::get_output_size() {
+                let screen = Monitor::whole_screen(width, height);
+                return Self::with_screen(width, height, vec![screen], screen);
+            }
+        }
+        let (screen_width, screen_height) = x11::get_screen_size()?;
+
+        // Without RandR, treat the whole screen as a single monitor
+        let mut monitors = x11::get_monitors().unwrap_or_default();
+        if monitors.is_empty() {
+            monitors.push(Monitor::whole_screen(screen_width, screen_height));
+        }
+
+        // Keep the work area on screen, whatever the window manager reports;
+        // window managers without _NET_WORKAREA, such as i3, leave it whole
+        let screen = Monitor::whole_screen(screen_width, screen_height);
+        let work_area = match x11::get_work_area() {
+            Ok((x, y, width, height)) => Monitor {
+                x,
+                y,
+                width,
+                height,
+            }
+            .intersect(&screen)
+            .unwrap_or(screen),
+            Err(_) => screen,
         };
-        Ok(Self {
+
+        Self::with_screen(screen_width, screen_height, monitors, work_area)
+    }
+
+    /// Create the configuration for a screen, with the user settings applied
+    fn with_screen(
+        screen_width: i32,
+        screen_height: i32,
+        monitors: Vec<Monitor>,
+        work_area: Monitor,
+    ) -> Result<Self> {
+        let mut config = Self {
             screen_width,
             screen_height,
-            frame_margin: margin,
-            base_margin: margin,
-            coordinates_margin: margin,
-            cell_size: 64.0,
-            font_scale: 0.4,
-            ui_transparency: 10,
-            toggle_key: rdev::Key::ControlRight,
-            exit_key: eframe::egui::Key::Escape,
-        })
+            monitors,
+            work_area,
+            ..Self::default()
+        };
+
+        // Apply user settings on top of the defaults
+        if let Some(path) = config_path() {
+            config.apply_file(&path)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Apply the settings from a TOML config file, if it exists
+    pub fn apply_file(&mut self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let contents = std::fs::read_to_string(path)?;
+        let file: ConfigFile = toml::from_str(&contents)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+
+        self.apply(file)
+    }
+
+    /// Validate and apply settings from the config file or the command line
+    pub fn apply(&mut self, file: ConfigFile) -> Result<()> {
+        if let Some(cell_size) = file.cell_size {
+            if !cell_size.is_finite() || cell_size < 8.0 {
+                return Err(Error::Config(format!(
+                    "cell_size must be at least 8.0, got {}",
+                    cell_size
+                )));
+            }
+            self.cell_size = cell_size;
+        }
+
+        if let Some(font_scale) = file.font_scale {
+            if !font_scale.is_finite() || font_scale <= 0.0 || font_scale > 1.0 {
+                return Err(Error::Config(format!(
+                    "font_scale must be in (0.0, 1.0], got {}",
+                    font_scale
+                )));
+            }
+            self.font_scale = font_scale;
+        }
+
+        if let Some(micro_font_scale) = file.micro_font_scale {
+            if !micro_font_scale.is_finite() || micro_font_scale <= 0.0 || micro_font_scale > 1.0 {
+                return Err(Error::Config(format!(
+                    "micro_font_scale must be in (0.0, 1.0], got {}",
+                    micro_font_scale
+                )));
+            }
+            self.micro_font_scale = micro_font_scale;
+        }
+
+        if let Some(ui_transparency) = file.ui_transparency {
+            self.ui_transparency = ui_transparency;
+        }
+
+        if let Some(name) = file.theme {
+            self.theme = ThemePreset::from_name(&name)
+                .ok_or_else(|| Error::Config(format!("unknown theme \"{}\"", name)))?;
+        }
+
+        for (name, value, color) in [
+            ("tint_color", file.tint_color, &mut self.tint_color),
+            (
+                "grid_line_color",
+                file.grid_line_color,
+                &mut self.grid_line_color,
+            ),
+            ("label_color", file.label_color, &mut self.label_color),
+            ("badge_color", file.badge_color, &mut self.badge_color),
+            (
+                "badge_text_color",
+                file.badge_text_color,
+                &mut self.badge_text_color,
+            ),
+            ("prefix_color", file.prefix_color, &mut self.prefix_color),
+        ] {
+            if let Some(value) = value {
+                *color = Some(theme::parse_color(&value).ok_or_else(|| {
+                    Error::Config(format!(
+                        "{} must be #RRGGBB or #RRGGBBAA, got \"{}\"",
+                        name, value
+                    ))
+                })?);
+            }
+        }
+
+        if let Some(name) = file.keyboard_layout {
+            self.alphabets = Alphabets::for_layout(&name)
+                .ok_or_else(|| Error::Config(format!("unknown keyboard_layout \"{}\"", name)))?;
+        }
+
+        if let Some(hint_alphabet) = file.hint_alphabet {
+            self.alphabets.hint = Alphabets::parse(&hint_alphabet);
+        }
+
+        if let Some(micro_alphabet) = file.micro_alphabet {
+            self.alphabets.micro = Alphabets::parse(&micro_alphabet);
+        }
+
+        if let Some(name) = file.label_order {
+            self.alphabets.order = LabelOrder::from_name(&name)
+                .ok_or_else(|| Error::Config(format!("unknown label_order \"{}\"", name)))?;
+        }
+
+        self.alphabets.validate()?;
+
+        if let Some(recursive_micro_grid) = file.recursive_micro_grid {
+            self.recursive_micro_grid = recursive_micro_grid;
+        }
+
+        if let Some(confirm_click) = file.confirm_click {
+            self.confirm_click = confirm_click;
+        }
+
+        if let Some(grab_keyboard) = file.grab_keyboard {
+            self.grab_keyboard = grab_keyboard;
+        }
+
+        if let Some(name) = file.click_action {
+            self.click_action = MouseAction::from_name(&name)
+                .ok_or_else(|| Error::Config(format!("unknown click_action \"{}\"", name)))?;
+        }
+
+        if let Some(name) = file.window_action {
+            self.window_action = WindowAction::from_name(&name)
+                .ok_or_else(|| Error::Config(format!("unknown window_action \"{}\"", name)))?;
+        }
+
+        if let Some(name) = file.pointer_backend {
+            self.pointer_backend = PointerBackendKind::from_name(&name)
+                .ok_or_else(|| Error::Config(format!("unknown pointer_backend \"{}\"", name)))?;
+        }
+
+        if let Some(scroll_step) = file.scroll_step {
+            if scroll_step < 1 {
+                return Err(Error::Config(format!(
+                    "scroll_step must be at least 1, got {}",
+                    scroll_step
+                )));
+            }
+            self.scroll_step = scroll_step;
+        }
+
+        if let Some(scroll_acceleration) = file.scroll_acceleration {
+            if !scroll_acceleration.is_finite() || scroll_acceleration < 0.0 {
+                return Err(Error::Config(format!(
+                    "scroll_acceleration must not be negative, got {}",
+                    scroll_acceleration
+                )));
+            }
+            self.scroll_acceleration = scroll_acceleration;
+        }
+
+        if let Some(name) = file.toggle_key {
+            self.toggle_key = Hotkey::parse(&name)
+                .ok_or_else(|| Error::Config(format!("unknown toggle_key \"{}\"", name)))?;
+        }
+
+        if let Some(name) = file.move_toggle_key {
+            self.move_toggle_key =
+                Some(Hotkey::parse(&name).ok_or_else(|| {
+                    Error::Config(format!("unknown move_toggle_key \"{}\"", name))
+                })?);
+        }
+
+        if let Some(name) = file.hint_toggle_key {
+            self.hint_toggle_key = Hotkey::parse(&name)
+                .ok_or_else(|| Error::Config(format!("unknown hint_toggle_key \"{}\"", name)))?;
+        }
+
+        if let Some(name) = file.window_toggle_key {
+            self.window_toggle_key = Hotkey::parse(&name)
+                .ok_or_else(|| Error::Config(format!("unknown window_toggle_key \"{}\"", name)))?;
+        }
+
+        if let Some(name) = file.exit_key {
+            self.exit_key = eframe::egui::Key::from_name(&name)
+                .ok_or_else(|| Error::Config(format!("unknown exit_key \"{}\"", name)))?;
+        }
+
+        if let Some(name) = file.drag_key {
+            self.drag_key = eframe::egui::Key::from_name(&name)
+                .ok_or_else(|| Error::Config(format!("unknown drag_key \"{}\"", name)))?;
+        }
+
+        if let Some(name) = file.scroll_key {
+            self.scroll_key = eframe::egui::Key::from_name(&name)
+                .ok_or_else(|| Error::Config(format!("unknown scroll_key \"{}\"", name)))?;
+        }
+
+        Ok(())
     }
 }
 
@@ -65,14 +404,193 @@ impl Default for AppConfig {
         Self {
             screen_width: 1920,
             screen_height: 1080,
-            frame_margin: Margin::default(),
-            coordinates_margin: Margin::default(),
-            base_margin: Margin::default(),
+            monitors: vec![Monitor::whole_screen(1920, 1080)],
+            work_area: Monitor::whole_screen(1920, 1080),
             cell_size: 64.0,
             font_scale: 0.4,
+            micro_font_scale: 0.4,
             ui_transparency: 10,
-            toggle_key: rdev::Key::ControlRight,
+            theme: ThemePreset::default(),
+            tint_color: None,
+            grid_line_color: None,
+            label_color: None,
+            badge_color: None,
+            badge_text_color: None,
+            prefix_color: None,
+            alphabets: Alphabets::default(),
+            recursive_micro_grid: false,
+            confirm_click: false,
+            grab_keyboard: false,
+            click_action: MouseAction::default(),
+            window_action: WindowAction::default(),
+            pointer_backend: PointerBackendKind::default(),
+            scroll_step: 1,
+            scroll_acceleration: 0.5,
+            toggle_key: Hotkey::single(rdev::Key::ControlRight),
+            move_toggle_key: None,
+            hint_toggle_key: Hotkey::single(rdev::Key::MetaRight),
+            window_toggle_key: Hotkey::single(rdev::Key::ScrollLock),
             exit_key: eframe::egui::Key::Escape,
+            drag_key: eframe::egui::Key::Tab,
+            scroll_key: eframe::egui::Key::Backtick,
+        }
+    }
+}
+
+/// Configuration shared between the UI and the input threads
+///
+/// Reloading rebuilds the configuration from the system and the config
+/// file, then applies the command-line overrides again.
+#[derive(Debug, Clone)]
+pub struct SharedConfig {
+    current: Arc<Mutex<AppConfig>>,
+    changed: Arc<Mutex<bool>>,
+    overrides: Arc<ConfigFile>,
+}
+
+impl SharedConfig {
+    /// Share a loaded configuration and the overrides it was built with
+    pub fn new(config: AppConfig, overrides: ConfigFile) -> Self {
+        Self {
+            current: Arc::new(Mutex::new(config)),
+            changed: Arc::new(Mutex::new(false)),
+            overrides: Arc::new(overrides),
+        }
+    }
+
+    /// Lock the current configuration
+    pub fn lock(&self) -> MutexGuard<'_, AppConfig> {
+        self.current.lock().unwrap()
+    }
+
+    /// Rebuild the configuration, keeping the current one if it is invalid
+    pub fn reload(&self) -> Result<()> {
+        let mut config = AppConfig::load()?;
+        config.apply((*self.overrides).clone())?;
+
+        *self.lock() = config;
+        *self.changed.lock().unwrap() = true;
+
+        // Let the UI pick it up even while it is idle
+        if let Some(ctx) = CTX_CELL.get() {
+            ctx.request_repaint();
+        }
+        Ok(())
+    }
+
+    /// Get the configuration if it changed since the last call
+    pub fn take_changed(&self) -> Option<AppConfig> {
+        let changed = std::mem::take(&mut *self.changed.lock().unwrap());
+        changed.then(|| self.lock().clone())
+    }
+}
+
+/// Path of the user config file, `$XDG_CONFIG_HOME/kmouse/config.toml`
+///
+/// Falls back to `$HOME/.config` when `XDG_CONFIG_HOME` is unset or not absolute.
+pub fn config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("kmouse").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply a config file given as a TOML string to the defaults
+    fn apply_toml(contents: &str) -> Result<AppConfig> {
+        let file: ConfigFile =
+            toml::from_str(contents).map_err(|e| Error::Config(e.to_string()))?;
+        let mut config = AppConfig::default();
+        config.apply(file)?;
+        Ok(config)
+    }
+
+    #[test]
+    fn valid_settings_are_applied() {
+        let config = apply_toml(
+            r#"
+            cell_size = 8.0
+            font_scale = 1.0
+            toggle_key = "Ctrl+Alt+M"
+            exit_key = "Q"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.cell_size, 8.0);
+        assert_eq!(config.font_scale, 1.0);
+        assert_eq!(config.toggle_key, Hotkey::parse("Ctrl+Alt+M").unwrap());
+        assert_eq!(config.exit_key, eframe::egui::Key::Q);
+    }
+
+    #[test]
+    fn cell_size_must_be_finite_and_large_enough() {
+        for value in ["7.9", "0.0", "-64.0", "nan", "inf"] {
+            let result = apply_toml(&format!("cell_size = {}", value));
+            assert!(
+                matches!(result, Err(Error::Config(_))),
+                "cell_size = {}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn font_scale_must_be_in_unit_interval() {
+        for value in ["0.0", "-0.5", "1.01", "nan", "inf"] {
+            for key in ["font_scale", "micro_font_scale"] {
+                let result = apply_toml(&format!("{} = {}", key, value));
+                assert!(
+                    matches!(result, Err(Error::Config(_))),
+                    "{} = {}",
+                    key,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        for setting in [
+            r#"toggle_key = "NoSuchKey""#,
+            r#"toggle_key = "Ctrl+""#,
+            r#"toggle_key = """#,
+            r#"exit_key = "NoSuchKey""#,
+            r#"exit_key = "ControlRight""#,
+        ] {
+            let result = apply_toml(setting);
+            assert!(matches!(result, Err(Error::Config(_))), "{}", setting);
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let result = toml::from_str::<ConfigFile>("cell_sise = 64.0");
+        assert!(result.is_err());
+
+        let path = std::env::temp_dir().join(format!("kmouse-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "cell_size = 48.0\ncell_sise = 64.0\n").unwrap();
+        let mut config = AppConfig::default();
+        let result = config.apply_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(Error::Config(message)) => assert!(message.contains("cell_sise"), "{}", message),
+            other => panic!("expected a config error, got {:?}", other),
         }
+        assert_eq!(config.cell_size, 64.0);
+    }
+
+    #[test]
+    fn missing_config_file_keeps_defaults() {
+        let mut config = AppConfig::default();
+        let path = std::env::temp_dir().join("kmouse-test-missing/config.toml");
+        config.apply_file(&path).unwrap();
+        assert_eq!(config.cell_size, AppConfig::default().cell_size);
     }
 }
@@ -9,10 +9,18 @@ pub enum Error {
     X11(String),
     /// Input device errors
     Input(String),
-    /// UI errors
-    Ui(String),
     /// IO errors
     Io(std::io::Error),
+    /// Configuration errors
+    Config(String),
+    /// Accessibility bus errors
+    Accessibility(String),
+    /// Control socket errors
+    Ipc(String),
+    /// Wayland connection errors
+    Wayland(String),
+    /// Overlay window errors
+    Ui(String),
 }
 
 impl fmt::Display for Error {
@@ -20,8 +28,12 @@ impl fmt::Display for Error {
         match self {
             Error::X11(msg) => write!(f, "X11 error: {}", msg),
             Error::Input(msg) => write!(f, "Input error: {}", msg),
-            Error::Ui(msg) => write!(f, "UI error: {}", msg),
             Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Config(msg) => write!(f, "Config error: {}", msg),
+            Error::Accessibility(msg) => write!(f, "Accessibility error: {}", msg),
+            Error::Ipc(msg) => write!(f, "IPC error: {}", msg),
+            Error::Wayland(msg) => write!(f, "Wayland error: {}", msg),
+            Error::Ui(msg) => write!(f, "UI error: {}", msg),
         }
     }
 }
@@ -34,6 +46,12 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<x11rb::errors::ConnectError> for Error {
+    fn from(err: x11rb::errors::ConnectError) -> Self {
+        Error::X11(err.to_string())
+    }
+}
+
 impl From<x11rb::errors::ConnectionError> for Error {
     fn from(err: x11rb::errors::ConnectionError) -> Self {
         Error::X11(err.to_string())
@@ -46,6 +64,12 @@ impl From<x11rb::errors::ReplyError> for Error {
     }
 }
 
+impl From<enigo::NewConError> for Error {
+    fn from(err: enigo::NewConError) -> Self {
+        Error::Input(err.to_string())
+    }
+}
+
 impl From<enigo::InputError> for Error {
     fn from(err: enigo::InputError) -> Self {
         Error::Input(err.to_string())
@@ -58,5 +82,47 @@ impl From<rdev::ListenError> for Error {
     }
 }
 
+impl From<zbus::Error> for Error {
+    fn from(err: zbus::Error) -> Self {
+        Error::Accessibility(err.to_string())
+    }
+}
+
+impl From<wayland_client::ConnectError> for Error {
+    fn from(err: wayland_client::ConnectError) -> Self {
+        Error::Wayland(err.to_string())
+    }
+}
+
+impl From<wayland_client::globals::GlobalError> for Error {
+    fn from(err: wayland_client::globals::GlobalError) -> Self {
+        Error::Wayland(err.to_string())
+    }
+}
+
+impl From<wayland_client::globals::BindError> for Error {
+    fn from(err: wayland_client::globals::BindError) -> Self {
+        Error::Wayland(err.to_string())
+    }
+}
+
+impl From<wayland_client::backend::WaylandError> for Error {
+    fn from(err: wayland_client::backend::WaylandError) -> Self {
+        Error::Wayland(err.to_string())
+    }
+}
+
+impl From<wayland_client::DispatchError> for Error {
+    fn from(err: wayland_client::DispatchError) -> Self {
+        Error::Wayland(err.to_string())
+    }
+}
+
+impl From<eframe::Error> for Error {
+    fn from(err: eframe::Error) -> Self {
+        Error::Ui(err.to_string())
+    }
+}
+
 /// Result type alias for the application
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,299 @@
+//! Global hotkeys: single keys, modifier chords and double taps
+
+use std::time::{Duration, Instant};
+
+use crate::input::keyboard;
+
+/// Second press of a double tap must come within this interval
+const DOUBLE_TAP_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Prefix of the double tap syntax, e.g. `double:ControlRight`
+const DOUBLE_TAP_PREFIX: &str = "double:";
+
+/// Modifier keys held during a chord
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChordModifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+impl ChordModifiers {
+    /// Set the modifier named in a chord, e.g. "Ctrl" or "Super"
+    fn set(&mut self, name: &str) -> Option<()> {
+        match name {
+            "Ctrl" | "Control" => self.ctrl = true,
+            "Alt" => self.alt = true,
+            "Shift" => self.shift = true,
+            "Super" | "Meta" => self.meta = true,
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Set the modifier of a held key, if it is one
+    fn add(&mut self, key: rdev::Key) {
+        use rdev::Key as K;
+
+        match key {
+            K::ControlLeft | K::ControlRight => self.ctrl = true,
+            K::Alt => self.alt = true,
+            K::ShiftLeft | K::ShiftRight => self.shift = true,
+            K::MetaLeft | K::MetaRight => self.meta = true,
+            _ => {}
+        }
+    }
+}
+
+/// A global hotkey from the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    /// `key` pressed while exactly `modifiers` are held, e.g. `Ctrl+Alt+M`
+    Chord {
+        modifiers: ChordModifiers,
+        key: rdev::Key,
+    },
+    /// `key` pressed twice in a row, e.g. `double:ControlRight`
+    DoubleTap(rdev::Key),
+}
+
+impl Hotkey {
+    /// A single key without modifiers
+    pub fn single(key: rdev::Key) -> Self {
+        Self::Chord {
+            modifiers: ChordModifiers::default(),
+            key,
+        }
+    }
+
+    /// Parse a hotkey such as "F12", "Super+;", "Ctrl+Alt+M" or
+    /// "double:ControlRight"
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Some(name) = text.strip_prefix(DOUBLE_TAP_PREFIX) {
+            return keyboard::rdev_key_from_name(name).map(Self::DoubleTap);
+        }
+
+        // The last part is the key, the others are modifiers
+        let (modifier_names, name) = match text.rsplit_once('+') {
+            Some((modifier_names, name)) => (Some(modifier_names), name),
+            None => (None, text),
+        };
+        let mut modifiers = ChordModifiers::default();
+        for modifier in modifier_names
+            .into_iter()
+            .flat_map(|names| names.split('+'))
+        {
+            modifiers.set(modifier)?;
+        }
+
+        Some(Self::Chord {
+            modifiers,
+            key: keyboard::rdev_key_from_name(name)?,
+        })
+    }
+}
+
+/// Key state tracked from key press and release events
+#[derive(Debug, Clone, Default)]
+pub struct HotkeyState {
+    /// Keys currently held down
+    held: Vec<rdev::Key>,
+    /// The latest press and the one before it, for double taps
+    last: Option<(rdev::Key, Instant)>,
+    previous: Option<(rdev::Key, Instant)>,
+}
+
+impl HotkeyState {
+    /// Record a key press; returns false for auto-repeated presses
+    pub fn press(&mut self, key: rdev::Key) -> bool {
+        if self.held.contains(&key) {
+            return false;
+        }
+
+        self.held.push(key);
+        self.previous = self.last.replace((key, Instant::now()));
+        true
+    }
+
+    /// Record a key release
+    pub fn release(&mut self, key: rdev::Key) {
+        self.held.retain(|&held| held != key);
+    }
+
+    /// Check if the press of `key` just recorded triggers `hotkey`
+    pub fn matches(&self, hotkey: &Hotkey, key: rdev::Key) -> bool {
+        match *hotkey {
+            Hotkey::Chord { modifiers, key: k } => {
+                k == key && self.modifiers_except(key) == modifiers
+            }
+            Hotkey::DoubleTap(k) => {
+                let tapped = match (self.previous, self.last) {
+                    (Some((first, at)), Some((second, now))) => {
+                        first == k && second == k && now - at <= DOUBLE_TAP_INTERVAL
+                    }
+                    _ => false,
+                };
+                k == key && tapped && self.modifiers_except(key) == ChordModifiers::default()
+            }
+        }
+    }
+
+    /// Forget the recorded taps, so a triple tap does not fire twice
+    pub fn clear_taps(&mut self) {
+        self.last = None;
+        self.previous = None;
+    }
+
+    /// Get the modifiers held, not counting `key` itself
+    fn modifiers_except(&self, key: rdev::Key) -> ChordModifiers {
+        let mut modifiers = ChordModifiers::default();
+        for &held in self.held.iter().filter(|&&held| held != key) {
+            modifiers.add(held);
+        }
+        modifiers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdev::Key as K;
+
+    fn chord(ctrl: bool, alt: bool, shift: bool, meta: bool, key: rdev::Key) -> Hotkey {
+        Hotkey::Chord {
+            modifiers: ChordModifiers {
+                ctrl,
+                alt,
+                shift,
+                meta,
+            },
+            key,
+        }
+    }
+
+    #[test]
+    fn parse_hotkeys() {
+        for (text, expected) in [
+            ("F12", Hotkey::single(K::F12)),
+            ("m", Hotkey::single(K::KeyM)),
+            ("Super+;", chord(false, false, false, true, K::SemiColon)),
+            ("Ctrl+Alt+M", chord(true, true, false, false, K::KeyM)),
+            ("Alt+Ctrl+M", chord(true, true, false, false, K::KeyM)),
+            (
+                "Control+Shift+Meta+F1",
+                chord(true, false, true, true, K::F1),
+            ),
+            ("double:ControlRight", Hotkey::DoubleTap(K::ControlRight)),
+        ] {
+            assert_eq!(Hotkey::parse(text), Some(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_unknown_names_and_empty_chords() {
+        for text in [
+            "",
+            "+",
+            "Ctrl+",
+            "+M",
+            "Ctrl++M",
+            "Hyper+M",
+            "Ctrl+NoSuchKey",
+            "NoSuchKey",
+            "double:",
+            "double:NoSuchKey",
+        ] {
+            assert_eq!(Hotkey::parse(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn chords_match_exactly_the_held_modifiers() {
+        let hotkey = Hotkey::parse("Ctrl+Alt+M").unwrap();
+        let mut state = HotkeyState::default();
+
+        // Without modifiers
+        assert!(state.press(K::KeyM));
+        assert!(!state.matches(&hotkey, K::KeyM));
+        state.release(K::KeyM);
+
+        // With both modifiers, either side of Ctrl
+        assert!(state.press(K::ControlRight));
+        assert!(state.press(K::Alt));
+        assert!(state.press(K::KeyM));
+        assert!(state.matches(&hotkey, K::KeyM));
+        assert!(!state.matches(&hotkey, K::KeyN));
+
+        // Auto-repeat is not a new press
+        assert!(!state.press(K::KeyM));
+        state.release(K::KeyM);
+
+        // An extra modifier
+        assert!(state.press(K::ShiftLeft));
+        assert!(state.press(K::KeyM));
+        assert!(!state.matches(&hotkey, K::KeyM));
+        state.release(K::KeyM);
+        state.release(K::ShiftLeft);
+
+        // A released modifier
+        state.release(K::Alt);
+        assert!(state.press(K::KeyM));
+        assert!(!state.matches(&hotkey, K::KeyM));
+    }
+
+    #[test]
+    fn single_modifier_key_matches_itself() {
+        let hotkey = Hotkey::single(K::ControlRight);
+        let mut state = HotkeyState::default();
+
+        assert!(state.press(K::ControlRight));
+        assert!(state.matches(&hotkey, K::ControlRight));
+    }
+
+    #[test]
+    fn double_tap_inside_the_interval() {
+        let hotkey = Hotkey::DoubleTap(K::ControlRight);
+        let mut state = HotkeyState::default();
+
+        assert!(state.press(K::ControlRight));
+        assert!(!state.matches(&hotkey, K::ControlRight));
+        state.release(K::ControlRight);
+
+        assert!(state.press(K::ControlRight));
+        assert!(state.matches(&hotkey, K::ControlRight));
+        state.release(K::ControlRight);
+
+        // A third tap right after a fired double tap starts over
+        state.clear_taps();
+        assert!(state.press(K::ControlRight));
+        assert!(!state.matches(&hotkey, K::ControlRight));
+    }
+
+    #[test]
+    fn double_tap_outside_the_interval() {
+        let hotkey = Hotkey::DoubleTap(K::ControlRight);
+        let mut state = HotkeyState::default();
+
+        assert!(state.press(K::ControlRight));
+        state.release(K::ControlRight);
+
+        // Move the first tap back past the interval
+        let (key, at) = state.last.unwrap();
+        state.last = Some((key, at - DOUBLE_TAP_INTERVAL - Duration::from_millis(1)));
+
+        assert!(state.press(K::ControlRight));
+        assert!(!state.matches(&hotkey, K::ControlRight));
+    }
+
+    #[test]
+    fn double_tap_needs_the_same_key_twice() {
+        let hotkey = Hotkey::DoubleTap(K::ControlRight);
+        let mut state = HotkeyState::default();
+
+        assert!(state.press(K::ControlLeft));
+        state.release(K::ControlLeft);
+        assert!(state.press(K::ControlRight));
+        assert!(!state.matches(&hotkey, K::ControlRight));
+    }
+}
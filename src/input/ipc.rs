@@ -0,0 +1,252 @@
+//! Unix socket commands for controlling a running instance
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::config::SharedConfig;
+use crate::error::{Error, Result};
+use crate::input::mouse::MouseAction;
+use crate::input::overlay::{Overlay, OverlayMode, PointerRequest};
+
+/// How long a client may take to send its command or read the reply
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait for the UI thread to perform a mouse action
+const POINTER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A command sent over the socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcCommand {
+    /// Show the overlay in a mode
+    Show(OverlayMode),
+    /// Hide the overlay
+    Hide,
+    /// Hide the overlay if it is visible, otherwise show it in a mode
+    Toggle(OverlayMode),
+    /// Perform a mouse action at screen coordinates
+    ClickAt(i32, i32, MouseAction),
+    /// Reload the config file
+    ReloadConfig,
+}
+
+impl IpcCommand {
+    /// Parse a command line such as `click-at 100 200 right` or `mode scroll`
+    pub fn parse(line: &str) -> Result<Self> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let mode = |name: Option<&&str>| match name {
+            Some(name) => OverlayMode::from_name(name)
+                .ok_or_else(|| Error::Ipc(format!("unknown mode \"{}\"", name))),
+            None => Ok(OverlayMode::Grid),
+        };
+        let coordinate = |value: Option<&&str>| {
+            value
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| Error::Ipc("click-at needs X and Y coordinates".into()))
+        };
+
+        match words.first().copied() {
+            Some("show" | "mode") => Ok(Self::Show(mode(words.get(1))?)),
+            Some("hide") => Ok(Self::Hide),
+            Some("toggle") => Ok(Self::Toggle(mode(words.get(1))?)),
+            Some("click-at") => {
+                let x = coordinate(words.get(1))?;
+                let y = coordinate(words.get(2))?;
+                let action = match words.get(3) {
+                    Some(name) => MouseAction::from_name(name)
+                        .ok_or_else(|| Error::Ipc(format!("unknown action \"{}\"", name)))?,
+                    None => MouseAction::LeftClick,
+                };
+                Ok(Self::ClickAt(x, y, action))
+            }
+            Some("reload-config") => Ok(Self::ReloadConfig),
+            _ => Err(Error::Ipc(format!("unknown command \"{}\"", line.trim()))),
+        }
+    }
+}
+
+/// Path of the control socket, `$XDG_RUNTIME_DIR/kmouse.sock`
+pub fn socket_path() -> Result<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join("kmouse.sock"))
+        .ok_or_else(|| Error::Ipc("XDG_RUNTIME_DIR is not set".into()))
+}
+
+/// The bound control socket, removed when dropped
+#[derive(Debug)]
+pub struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Start listening for commands on the control socket
+///
+/// The socket file is removed when the returned handle is dropped.
+pub fn start_ipc_listener(overlay: Overlay, config: SharedConfig) -> Result<SocketFile> {
+    let path = socket_path()?;
+
+    // A socket nobody answers on is left over from a crashed instance
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(Error::Ipc(format!(
+                "another instance is listening on {}",
+                path.display()
+            )));
+        }
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(Error::from)
+                .and_then(|stream| handle_client(stream, &overlay, &config));
+            if let Err(e) = result {
+                eprintln!("Failed to handle IPC client: {}", e);
+            }
+        }
+    });
+
+    Ok(SocketFile(path))
+}
+
+/// Run the command of one client and answer `ok` or `error: <reason>`
+fn handle_client(stream: UnixStream, overlay: &Overlay, config: &SharedConfig) -> Result<()> {
+    // Clients are served one at a time, so a silent one must not hold the rest
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let reply = match IpcCommand::parse(&line).and_then(|command| execute(command, overlay, config))
+    {
+        Ok(()) => "ok".to_string(),
+        Err(e) => format!("error: {}", e),
+    };
+    writeln!(&stream, "{}", reply)?;
+
+    Ok(())
+}
+
+/// Execute a command against the overlay
+fn execute(command: IpcCommand, overlay: &Overlay, config: &SharedConfig) -> Result<()> {
+    match command {
+        IpcCommand::Show(mode) => overlay.show(mode),
+        IpcCommand::Hide => overlay.hide(),
+        IpcCommand::Toggle(mode) => overlay.toggle(mode),
+        IpcCommand::ClickAt(x, y, action) => {
+            // Only the UI thread can send mouse events; wait for its result
+            let (reply, result) = mpsc::channel();
+            overlay.request_pointer(PointerRequest::ActAt(x, y, action, reply));
+            result
+                .recv_timeout(POINTER_TIMEOUT)
+                .map_err(|_| Error::Ipc("timed out waiting for the mouse action".into()))??;
+        }
+        IpcCommand::ReloadConfig => config.reload()?,
+    }
+
+    Ok(())
+}
+
+/// Send a command to the running instance and return its reply
+pub fn send_command(command: &str) -> Result<String> {
+    let path = socket_path()?;
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|e| Error::Ipc(format!("cannot reach kmouse at {}: {}", path.display(), e)))?;
+
+    writeln!(stream, "{}", command)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+
+    Ok(reply.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AppConfig, ConfigFile};
+    use std::time::Instant;
+
+    #[test]
+    fn commands_parse() {
+        assert_eq!(
+            IpcCommand::parse("click-at 100 200 right\n").unwrap(),
+            IpcCommand::ClickAt(100, 200, MouseAction::RightClick)
+        );
+        assert_eq!(
+            IpcCommand::parse("toggle").unwrap(),
+            IpcCommand::Toggle(OverlayMode::Grid)
+        );
+        assert_eq!(
+            IpcCommand::parse("mode scroll").unwrap(),
+            IpcCommand::Show(OverlayMode::Scroll)
+        );
+        assert!(IpcCommand::parse("click-at 100").is_err());
+        assert!(IpcCommand::parse("show nowhere").is_err());
+    }
+
+    #[test]
+    fn click_at_reports_the_pointer_result() {
+        let overlay = Overlay::new();
+        let config = SharedConfig::new(AppConfig::default(), ConfigFile::default());
+
+        // Stand in for the UI thread, failing the first action
+        let ui = overlay.clone();
+        std::thread::spawn(move || {
+            for succeed in [false, true] {
+                loop {
+                    if let Some(PointerRequest::ActAt(_, _, _, reply)) =
+                        ui.take_pointer_requests().pop()
+                    {
+                        let result = if succeed {
+                            Ok(())
+                        } else {
+                            Err(Error::Input("mouse control unavailable".into()))
+                        };
+                        reply.send(result).unwrap();
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+            }
+        });
+
+        let command = IpcCommand::ClickAt(100, 200, MouseAction::LeftClick);
+        let error = execute(command, &overlay, &config).unwrap_err();
+        assert!(error.to_string().contains("mouse control unavailable"));
+        assert!(execute(command, &overlay, &config).is_ok());
+    }
+
+    #[test]
+    fn click_at_times_out_without_the_ui() {
+        let overlay = Overlay::new();
+        let config = SharedConfig::new(AppConfig::default(), ConfigFile::default());
+
+        let started = Instant::now();
+        let command = IpcCommand::ClickAt(100, 200, MouseAction::LeftClick);
+        assert!(execute(command, &overlay, &config).is_err());
+        assert!(started.elapsed() >= POINTER_TIMEOUT);
+    }
+
+    #[test]
+    fn silent_client_times_out() {
+        let overlay = Overlay::new();
+        let config = SharedConfig::new(AppConfig::default(), ConfigFile::default());
+
+        // Connected, but never sending a line
+        let (_client, server) = UnixStream::pair().unwrap();
+        let started = Instant::now();
+        assert!(handle_client(server, &overlay, &config).is_err());
+        assert!(started.elapsed() < CLIENT_TIMEOUT * 2);
+    }
+}
@@ -1,47 +1,61 @@
 //! Keyboard event handling
 
+use crate::config::SharedConfig;
 use crate::error::Result;
-use crate::models::cell::FocusedCell;
+use crate::input::hotkey::HotkeyState;
+use crate::input::overlay::{Overlay, OverlayMode};
+use crate::ui::banner;
 use eframe::egui::{Context, Key};
 use once_cell::sync::OnceCell;
 use rdev::{listen, EventType};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 /// Global context cell for the UI
 pub static CTX_CELL: OnceCell<Arc<Context>> = OnceCell::new();
 
 /// Start listening for keyboard events
-pub fn start_keyboard_listener(
-    is_visible: Arc<Mutex<bool>>,
-    initiated: Arc<Mutex<bool>>,
-    focused_cell: Arc<Mutex<FocusedCell>>,
-    toggle_key: rdev::Key,
-) -> Result<()> {
+///
+/// Toggle hotkeys are read from `config` on every press, so reloads apply
+/// without restarting the listener.
+pub fn start_keyboard_listener(overlay: Overlay, config: SharedConfig) -> Result<()> {
     std::thread::spawn(move || {
+        let mut keys = HotkeyState::default();
         if let Err(error) = listen(move |event| {
-            let mut vis = is_visible.lock().unwrap();
-            let mut has_started = initiated.lock().unwrap();
-            let mut focused_cell = focused_cell.lock().unwrap();
-
-            if let EventType::KeyPress(key) = event.event_type {
-                if let Some(ctx) = CTX_CELL.get() {
-                    if key == toggle_key {
-                        *vis = !*vis;
-                        if *vis {
-                            if !*has_started {
-                                *has_started = true;
-                            }
-                            ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Visible(true));
-                            *focused_cell = FocusedCell::new();
-                        }
-                        if !*vis {
-                            ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Visible(false));
-                        }
-                    }
+            let key = match event.event_type {
+                EventType::KeyPress(key) => key,
+                EventType::KeyRelease(key) => {
+                    keys.release(key);
+                    return;
                 }
+                _ => return,
+            };
+            if !keys.press(key) {
+                return;
             }
+
+            // The move toggle key opens the overlay without clicking, the
+            // hint and window toggle keys label elements and windows
+            let mode = {
+                let config = config.lock();
+                if keys.matches(&config.toggle_key, key) {
+                    OverlayMode::Grid
+                } else if config
+                    .move_toggle_key
+                    .is_some_and(|hotkey| keys.matches(&hotkey, key))
+                {
+                    OverlayMode::Move
+                } else if keys.matches(&config.hint_toggle_key, key) {
+                    OverlayMode::Hints
+                } else if keys.matches(&config.window_toggle_key, key) {
+                    OverlayMode::Windows
+                } else {
+                    return;
+                }
+            };
+            keys.clear_taps();
+            overlay.toggle(mode);
         }) {
-            eprintln!("Error: {:?}", error);
+            banner::report_error("Failed to listen for toggle keys", format!("{:?}", error));
         }
     });
 
@@ -52,3 +66,151 @@ pub fn start_keyboard_listener(
 pub fn key_from_char(c: char) -> Option<Key> {
     eframe::egui::Key::from_name(&c.to_string())
 }
+
+/// Get the character of an egui key, the inverse of `key_from_char`
+pub fn char_from_key(key: Key) -> Option<char> {
+    [key.name(), key.symbol_or_name()]
+        .into_iter()
+        .find_map(|name| {
+            let mut chars = name.chars();
+            let c = chars.next()?;
+            (chars.next().is_none() && key_from_char(c) == Some(key)).then_some(c)
+        })
+}
+
+/// Get an egui key from an X keysym, for keys read from a keyboard grab or
+/// a layer-shell surface
+pub fn key_from_keysym(keysym: u32) -> Option<Key> {
+    let key = match keysym {
+        0xff08 => Key::Backspace,
+        0xff09 => Key::Tab,
+        0xff0d => Key::Enter,
+        0xff1b => Key::Escape,
+        0xff51 => Key::ArrowLeft,
+        0xff52 => Key::ArrowUp,
+        0xff53 => Key::ArrowRight,
+        0xff54 => Key::ArrowDown,
+        0x0020 => Key::Space,
+        0x0060 => Key::Backtick,
+        // Latin-1 keysyms match their characters
+        0x0021..=0x007e => return key_from_char((keysym as u8 as char).to_ascii_uppercase()),
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// Get an rdev key from its name, e.g. "ControlRight", "F12" or "A"
+pub fn rdev_key_from_name(name: &str) -> Option<rdev::Key> {
+    use rdev::Key as K;
+
+    let key = match name {
+        "Alt" => K::Alt,
+        "AltGr" => K::AltGr,
+        "Backspace" => K::Backspace,
+        "CapsLock" => K::CapsLock,
+        "ControlLeft" => K::ControlLeft,
+        "ControlRight" => K::ControlRight,
+        "Delete" => K::Delete,
+        "DownArrow" => K::DownArrow,
+        "End" => K::End,
+        "Escape" => K::Escape,
+        "F1" => K::F1,
+        "F2" => K::F2,
+        "F3" => K::F3,
+        "F4" => K::F4,
+        "F5" => K::F5,
+        "F6" => K::F6,
+        "F7" => K::F7,
+        "F8" => K::F8,
+        "F9" => K::F9,
+        "F10" => K::F10,
+        "F11" => K::F11,
+        "F12" => K::F12,
+        "Home" => K::Home,
+        "LeftArrow" => K::LeftArrow,
+        "MetaLeft" => K::MetaLeft,
+        "MetaRight" => K::MetaRight,
+        "PageDown" => K::PageDown,
+        "PageUp" => K::PageUp,
+        "Return" => K::Return,
+        "RightArrow" => K::RightArrow,
+        "ShiftLeft" => K::ShiftLeft,
+        "ShiftRight" => K::ShiftRight,
+        "Space" => K::Space,
+        "Tab" => K::Tab,
+        "UpArrow" => K::UpArrow,
+        "PrintScreen" => K::PrintScreen,
+        "ScrollLock" => K::ScrollLock,
+        "Pause" => K::Pause,
+        "NumLock" => K::NumLock,
+        "Insert" => K::Insert,
+        _ => return rdev_key_from_char(name),
+    };
+
+    Some(key)
+}
+
+/// Get an rdev key from a single letter or digit name
+fn rdev_key_from_char(name: &str) -> Option<rdev::Key> {
+    use rdev::Key as K;
+
+    let mut chars = name.chars();
+    let c = chars.next()?.to_ascii_uppercase();
+    if chars.next().is_some() {
+        return None;
+    }
+
+    let key = match c {
+        'A' => K::KeyA,
+        'B' => K::KeyB,
+        'C' => K::KeyC,
+        'D' => K::KeyD,
+        'E' => K::KeyE,
+        'F' => K::KeyF,
+        'G' => K::KeyG,
+        'H' => K::KeyH,
+        'I' => K::KeyI,
+        'J' => K::KeyJ,
+        'K' => K::KeyK,
+        'L' => K::KeyL,
+        'M' => K::KeyM,
+        'N' => K::KeyN,
+        'O' => K::KeyO,
+        'P' => K::KeyP,
+        'Q' => K::KeyQ,
+        'R' => K::KeyR,
+        'S' => K::KeyS,
+        'T' => K::KeyT,
+        'U' => K::KeyU,
+        'V' => K::KeyV,
+        'W' => K::KeyW,
+        'X' => K::KeyX,
+        'Y' => K::KeyY,
+        'Z' => K::KeyZ,
+        '0' => K::Num0,
+        '1' => K::Num1,
+        '2' => K::Num2,
+        '3' => K::Num3,
+        '4' => K::Num4,
+        '5' => K::Num5,
+        '6' => K::Num6,
+        '7' => K::Num7,
+        '8' => K::Num8,
+        '9' => K::Num9,
+        ';' => K::SemiColon,
+        ',' => K::Comma,
+        '.' => K::Dot,
+        '/' => K::Slash,
+        '\'' => K::Quote,
+        '`' => K::BackQuote,
+        '-' => K::Minus,
+        '=' => K::Equal,
+        '[' => K::LeftBracket,
+        ']' => K::RightBracket,
+        '\\' => K::BackSlash,
+        _ => return None,
+    };
+
+    Some(key)
+}
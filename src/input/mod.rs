@@ -1,4 +1,8 @@
 //! Input handling module
 
+pub mod hotkey;
+pub mod ipc;
 pub mod keyboard;
 pub mod mouse;
+pub mod overlay;
+pub mod pointer;
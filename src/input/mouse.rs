@@ -1,20 +1,363 @@
 //! Mouse control functions
 
-use crate::error::Result;
-use enigo::{Enigo, Mouse, Settings};
-
-/// Move the cursor to the specified coordinates and click
-pub fn move_cursor_to(x: i32, y: i32, enigo: &mut Enigo) -> Result<()> {
-    enigo
-        .move_mouse(x, y, enigo::Coordinate::Abs)
-        .expect("Error: enigo move mouse");
-    enigo
-        .button(enigo::Button::Left, enigo::Direction::Click)
-        .expect("Error: enigo mouse click");
-    Ok(())
-}
-
-/// Create a new Enigo instance
-pub fn create_enigo() -> Result<Enigo> {
-    Ok(Enigo::new(&Settings::default()).expect("Error: enigo instance"))
+use crate::error::{Error, Result};
+use crate::input::pointer::{PointerBackend, PointerBackendKind, PointerButton, ScrollAxis};
+use eframe::egui::Modifiers;
+use std::time::{Duration, Instant};
+
+/// Scroll presses further apart than this restart the acceleration
+const SCROLL_STREAK_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Bounds of the delay between attempts to reach the input backend
+const RETRY_MIN_BACKOFF: Duration = Duration::from_millis(500);
+const RETRY_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Upper bound for an accelerated scroll, as a multiple of the base step
+const SCROLL_MAX_MULTIPLIER: f32 = 10.0;
+
+/// Mouse action performed at the selected point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MouseAction {
+    #[default]
+    LeftClick,
+    RightClick,
+    MiddleClick,
+    DoubleClick,
+    /// Press the left button without releasing it (start of a drag)
+    Press,
+    /// Release the left button (end of a drag)
+    Release,
+    /// Only move the cursor
+    Move,
+}
+
+impl MouseAction {
+    /// Get an action from its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Self::LeftClick),
+            "right" => Some(Self::RightClick),
+            "middle" => Some(Self::MiddleClick),
+            "double" => Some(Self::DoubleClick),
+            "move" => Some(Self::Move),
+            _ => None,
+        }
+    }
+
+    /// Pick the action for the modifiers held on the final keypress
+    ///
+    /// Shift selects a right-click, Alt a middle-click and Ctrl a
+    /// double-click; without modifiers `default` is used.
+    pub fn from_modifiers(modifiers: &Modifiers, default: Self) -> Self {
+        if modifiers.shift {
+            Self::RightClick
+        } else if modifiers.alt {
+            Self::MiddleClick
+        } else if modifiers.ctrl {
+            Self::DoubleClick
+        } else {
+            default
+        }
+    }
+}
+
+/// Move the cursor to the specified coordinates and perform `action`
+pub fn move_cursor_to(
+    x: i32,
+    y: i32,
+    action: MouseAction,
+    pointer: &mut dyn PointerBackend,
+) -> Result<()> {
+    pointer.move_to(x, y)?;
+
+    match action {
+        MouseAction::LeftClick => pointer.click(PointerButton::Left),
+        MouseAction::RightClick => pointer.click(PointerButton::Right),
+        MouseAction::MiddleClick => pointer.click(PointerButton::Middle),
+        MouseAction::DoubleClick => {
+            pointer.click(PointerButton::Left)?;
+            pointer.click(PointerButton::Left)
+        }
+        MouseAction::Press => pointer.press(PointerButton::Left),
+        MouseAction::Release => pointer.release(PointerButton::Left),
+        MouseAction::Move => Ok(()),
+    }
+}
+
+/// Release the left button where the cursor is, cancelling a drag
+pub fn release_button(pointer: &mut dyn PointerBackend) -> Result<()> {
+    pointer.release(PointerButton::Left)
+}
+
+/// Direction of a scroll step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Sends scroll steps, speeding up while one direction is repeated
+#[derive(Debug, Clone)]
+pub struct Scroller {
+    step: i32,
+    acceleration: f32,
+    streak: u32,
+    last: Option<(ScrollDirection, Instant)>,
+}
+
+impl Scroller {
+    /// Create a scroller sending `step` notches, plus `acceleration`
+    /// notches per repeated press
+    pub fn new(step: i32, acceleration: f32) -> Self {
+        Self {
+            step,
+            acceleration,
+            streak: 0,
+            last: None,
+        }
+    }
+
+    /// Scroll under the cursor
+    pub fn scroll(
+        &mut self,
+        direction: ScrollDirection,
+        pointer: &mut dyn PointerBackend,
+    ) -> Result<()> {
+        let now = Instant::now();
+        self.streak = match self.last {
+            Some((last, at)) if last == direction && now - at < SCROLL_STREAK_TIMEOUT => {
+                self.streak + 1
+            }
+            _ => 0,
+        };
+        self.last = Some((direction, now));
+
+        let multiplier = (1.0 + self.acceleration * self.streak as f32).min(SCROLL_MAX_MULTIPLIER);
+        let amount = (self.step as f32 * multiplier).round() as i32;
+
+        // Positive lengths scroll down and right
+        let (length, axis) = match direction {
+            ScrollDirection::Up => (-amount, ScrollAxis::Vertical),
+            ScrollDirection::Down => (amount, ScrollAxis::Vertical),
+            ScrollDirection::Left => (-amount, ScrollAxis::Horizontal),
+            ScrollDirection::Right => (amount, ScrollAxis::Horizontal),
+        };
+        pointer.scroll(length, axis)
+    }
+}
+
+/// Pointer backend kept across frames, reconnected with backoff when it is
+/// unavailable or fails
+#[derive(Default)]
+pub struct Pointer {
+    kind: PointerBackendKind,
+    screen_size: (i32, i32),
+    backend: Option<Box<dyn PointerBackend>>,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl Pointer {
+    /// Create a pointer connecting a backend of `kind` on first use
+    pub fn new(kind: PointerBackendKind, screen_size: (i32, i32)) -> Self {
+        Self {
+            kind,
+            screen_size,
+            ..Self::default()
+        }
+    }
+
+    /// Switch to another backend kind or screen size, reconnecting on next use
+    pub fn configure(&mut self, kind: PointerBackendKind, screen_size: (i32, i32)) {
+        if kind != self.kind || screen_size != self.screen_size {
+            *self = Self::new(kind, screen_size);
+        }
+    }
+
+    /// Connect the backend if it is missing and no retry is pending
+    ///
+    /// Only the error of an attempt made by this call is returned, so a lost
+    /// backend is reported once per retry rather than on every frame.
+    pub fn reconnect(&mut self) -> Result<()> {
+        if self.backend.is_some() || self.retry_in().is_some_and(|delay| !delay.is_zero()) {
+            return Ok(());
+        }
+
+        match self.kind.connect(self.screen_size) {
+            Ok(backend) => {
+                self.backend = Some(backend);
+                self.backoff = Duration::ZERO;
+                self.retry_at = None;
+                Ok(())
+            }
+            Err(e) => {
+                self.schedule_retry();
+                Err(e)
+            }
+        }
+    }
+
+    /// Time until the next connection attempt, while the backend is missing
+    pub fn retry_in(&self) -> Option<Duration> {
+        self.retry_at
+            .filter(|_| self.backend.is_none())
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Drop the backend and wait longer than last time before reconnecting
+    fn schedule_retry(&mut self) {
+        self.backend = None;
+        self.backoff = (self.backoff * 2).clamp(RETRY_MIN_BACKOFF, RETRY_MAX_BACKOFF);
+        self.retry_at = Some(Instant::now() + self.backoff);
+    }
+
+    /// Send events through the backend, reconnecting later if it fails
+    fn send(&mut self, events: impl FnOnce(&mut dyn PointerBackend) -> Result<()>) -> Result<()> {
+        self.reconnect()?;
+        let Some(backend) = self.backend.as_deref_mut() else {
+            let delay = self.retry_in().unwrap_or_default();
+            return Err(Error::Input(format!(
+                "mouse control unavailable, retrying in {}s",
+                delay.as_secs() + 1
+            )));
+        };
+
+        let result = events(backend);
+        if result.is_err() {
+            self.schedule_retry();
+        }
+        result
+    }
+}
+
+impl PointerBackend for Pointer {
+    fn move_to(&mut self, x: i32, y: i32) -> Result<()> {
+        self.send(|backend| backend.move_to(x, y))
+    }
+
+    fn press(&mut self, button: PointerButton) -> Result<()> {
+        self.send(|backend| backend.press(button))
+    }
+
+    fn release(&mut self, button: PointerButton) -> Result<()> {
+        self.send(|backend| backend.release(button))
+    }
+
+    fn click(&mut self, button: PointerButton) -> Result<()> {
+        self.send(|backend| backend.click(button))
+    }
+
+    fn scroll(&mut self, length: i32, axis: ScrollAxis) -> Result<()> {
+        self.send(|backend| backend.scroll(length, axis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::pointer::{PointerEvent, RecordingBackend};
+
+    #[test]
+    fn actions_send_their_events() {
+        let cases = [
+            (
+                MouseAction::LeftClick,
+                vec![PointerEvent::Click(PointerButton::Left)],
+            ),
+            (
+                MouseAction::RightClick,
+                vec![PointerEvent::Click(PointerButton::Right)],
+            ),
+            (
+                MouseAction::MiddleClick,
+                vec![PointerEvent::Click(PointerButton::Middle)],
+            ),
+            (
+                MouseAction::DoubleClick,
+                vec![
+                    PointerEvent::Click(PointerButton::Left),
+                    PointerEvent::Click(PointerButton::Left),
+                ],
+            ),
+            (
+                MouseAction::Press,
+                vec![PointerEvent::Press(PointerButton::Left)],
+            ),
+            (
+                MouseAction::Release,
+                vec![PointerEvent::Release(PointerButton::Left)],
+            ),
+            (MouseAction::Move, vec![]),
+        ];
+
+        for (action, clicks) in cases {
+            let mut pointer = RecordingBackend::default();
+            move_cursor_to(640, 480, action, &mut pointer).unwrap();
+
+            let mut expected = vec![PointerEvent::MoveTo(640, 480)];
+            expected.extend(clicks);
+            assert_eq!(pointer.events, expected, "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn release_button_releases_left() {
+        let mut pointer = RecordingBackend::default();
+        release_button(&mut pointer).unwrap();
+        assert_eq!(pointer.events, [PointerEvent::Release(PointerButton::Left)]);
+    }
+
+    #[test]
+    fn scroll_streak_grows_the_length() {
+        let mut pointer = RecordingBackend::default();
+        let mut scroller = Scroller::new(2, 0.5);
+
+        // Presses well within the streak timeout of each other
+        for direction in [
+            ScrollDirection::Down,
+            ScrollDirection::Down,
+            ScrollDirection::Down,
+            ScrollDirection::Up,
+            ScrollDirection::Right,
+            ScrollDirection::Left,
+        ] {
+            scroller.scroll(direction, &mut pointer).unwrap();
+        }
+
+        // A new direction starts over
+        assert_eq!(
+            pointer.events,
+            [
+                PointerEvent::Scroll(2, ScrollAxis::Vertical),
+                PointerEvent::Scroll(3, ScrollAxis::Vertical),
+                PointerEvent::Scroll(4, ScrollAxis::Vertical),
+                PointerEvent::Scroll(-2, ScrollAxis::Vertical),
+                PointerEvent::Scroll(2, ScrollAxis::Horizontal),
+                PointerEvent::Scroll(-2, ScrollAxis::Horizontal),
+            ]
+        );
+    }
+
+    #[test]
+    fn scroll_length_is_capped() {
+        let mut pointer = RecordingBackend::default();
+        let mut scroller = Scroller::new(1, 4.0);
+        for _ in 0..5 {
+            scroller
+                .scroll(ScrollDirection::Down, &mut pointer)
+                .unwrap();
+        }
+
+        let lengths: Vec<i32> = pointer
+            .events
+            .iter()
+            .map(|event| match event {
+                PointerEvent::Scroll(length, _) => *length,
+                _ => 0,
+            })
+            .collect();
+        assert_eq!(lengths, [1, 5, 9, 10, 10]);
+    }
 }
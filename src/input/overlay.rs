@@ -0,0 +1,286 @@
+//! Overlay state shared between the UI and the input sources
+
+use crate::error::{Error, Result};
+use crate::input::keyboard::CTX_CELL;
+use crate::input::mouse::MouseAction;
+use crate::models::cell::{DragState, FocusedCell, ScrollState};
+use crate::models::hint::HintTarget;
+use crate::system::x11::KeyboardGrab;
+use crate::system::{atspi, x11};
+use crate::ui::banner;
+use eframe::egui::ViewportCommand;
+use std::io::ErrorKind;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// What the overlay offers when it is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlayMode {
+    /// The grid, clicking the selection
+    #[default]
+    Grid,
+    /// The grid, only moving the cursor
+    Move,
+    /// The grid with drag-and-drop armed
+    Drag,
+    /// The grid with scroll mode armed
+    Scroll,
+    /// Hints for the actionable elements of the active window
+    Hints,
+    /// Hints for the top-level windows
+    Windows,
+}
+
+impl OverlayMode {
+    /// Get a mode from its name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "grid" => Some(Self::Grid),
+            "move" => Some(Self::Move),
+            "drag" => Some(Self::Drag),
+            "scroll" => Some(Self::Scroll),
+            "hints" => Some(Self::Hints),
+            "windows" => Some(Self::Windows),
+            _ => None,
+        }
+    }
+}
+
+/// How long to wait for hint targets before falling back to the grid
+const TARGETS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A mouse action asked for off the UI thread
+#[derive(Debug)]
+pub enum PointerRequest {
+    /// Release the held button, cancelling a drag
+    ReleaseButton,
+    /// Perform an action at screen coordinates, sending back the result
+    ActAt(i32, i32, MouseAction, Sender<Result<()>>),
+}
+
+/// Handle to the overlay state
+///
+/// Locks are always taken in field order to avoid deadlocks between the UI
+/// and the input threads.
+#[derive(Debug, Clone)]
+pub struct Overlay {
+    /// Currently focused cell
+    pub focused_cell: Arc<Mutex<FocusedCell>>,
+    /// Visibility state
+    pub is_visible: Arc<Mutex<bool>>,
+    /// Counts show and hide requests, so late hint targets are dropped
+    pub shown: Arc<Mutex<u64>>,
+    /// Drag-and-drop progress
+    pub drag: Arc<Mutex<DragState>>,
+    /// Scroll mode progress
+    pub scroll: Arc<Mutex<ScrollState>>,
+    /// Whether this invocation only moves the cursor
+    pub move_only: Arc<Mutex<bool>>,
+    /// Elements labelled in hint mode; empty outside hint mode
+    pub hints: Arc<Mutex<Vec<HintTarget>>>,
+    /// Keyboard grab held while the overlay is visible, in exclusive mode
+    pub keyboard: Arc<Mutex<Option<KeyboardGrab>>>,
+    /// Mouse actions waiting for the UI thread, which owns the pointer
+    pub pointer_requests: Arc<Mutex<Vec<PointerRequest>>>,
+}
+
+impl Overlay {
+    /// Create the state of a visible overlay
+    pub fn new() -> Self {
+        Self {
+            focused_cell: Arc::new(Mutex::new(FocusedCell::new())),
+            is_visible: Arc::new(Mutex::new(true)),
+            shown: Arc::new(Mutex::new(0)),
+            drag: Arc::new(Mutex::new(DragState::default())),
+            scroll: Arc::new(Mutex::new(ScrollState::default())),
+            move_only: Arc::new(Mutex::new(false)),
+            hints: Arc::new(Mutex::new(Vec::new())),
+            keyboard: Arc::new(Mutex::new(None)),
+            pointer_requests: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Check if the overlay is visible
+    pub fn is_visible(&self) -> bool {
+        *self.is_visible.lock().unwrap()
+    }
+
+    /// Show the overlay in `mode`, starting a new selection
+    ///
+    /// Hint targets are collected on a worker first, and the overlay shows
+    /// once they arrive; the caller does not wait for them.
+    pub fn show(&self, mode: OverlayMode) {
+        let request = {
+            let mut shown = self.shown.lock().unwrap();
+            *shown += 1;
+            *shown
+        };
+
+        let (collect, context): (fn() -> Result<Vec<HintTarget>>, _) = match mode {
+            OverlayMode::Hints => (
+                atspi::get_actionable_elements,
+                "Failed to read accessible elements",
+            ),
+            OverlayMode::Windows => (x11::get_client_windows, "Failed to list windows"),
+            _ => return self.show_targets(mode, Vec::new(), request),
+        };
+
+        // Query before the overlay shows and takes over the active window;
+        // without targets, fall back to the grid
+        let overlay = self.clone();
+        std::thread::spawn(move || {
+            let targets = collect_targets(collect, TARGETS_TIMEOUT).unwrap_or_else(|e| {
+                banner::report_error(context, e);
+                Vec::new()
+            });
+            overlay.show_targets(mode, targets, request);
+        });
+    }
+
+    /// Show the overlay with its hint targets, unless it was shown or hidden
+    /// again since `request`
+    fn show_targets(&self, mode: OverlayMode, targets: Vec<HintTarget>, request: u64) {
+        let Some(ctx) = CTX_CELL.get() else {
+            return;
+        };
+
+        let mut focused_cell = self.focused_cell.lock().unwrap();
+        let mut is_visible = self.is_visible.lock().unwrap();
+        if *self.shown.lock().unwrap() != request {
+            return;
+        }
+        focused_cell.reset();
+        *is_visible = true;
+        self.cancel_drag();
+        if mode == OverlayMode::Drag {
+            *self.drag.lock().unwrap() = DragState::Armed;
+        }
+        *self.scroll.lock().unwrap() = if mode == OverlayMode::Scroll {
+            ScrollState::Armed
+        } else {
+            ScrollState::Idle
+        };
+        *self.move_only.lock().unwrap() = mode == OverlayMode::Move;
+        *self.hints.lock().unwrap() = targets;
+
+        ctx.send_viewport_cmd(ViewportCommand::Visible(true));
+    }
+
+    /// Hide the overlay, cancelling any selection in progress
+    pub fn hide(&self) {
+        let Some(ctx) = CTX_CELL.get() else {
+            return;
+        };
+
+        let mut is_visible = self.is_visible.lock().unwrap();
+        *is_visible = false;
+        *self.shown.lock().unwrap() += 1;
+        self.cancel_drag();
+        *self.scroll.lock().unwrap() = ScrollState::Idle;
+        self.hints.lock().unwrap().clear();
+        self.release_keyboard();
+
+        ctx.send_viewport_cmd(ViewportCommand::Visible(false));
+    }
+
+    /// Ask the UI thread to perform a mouse action
+    pub fn request_pointer(&self, request: PointerRequest) {
+        self.pointer_requests.lock().unwrap().push(request);
+        if let Some(ctx) = CTX_CELL.get() {
+            ctx.request_repaint();
+        }
+    }
+
+    /// Take the mouse actions asked for since the last call
+    pub fn take_pointer_requests(&self) -> Vec<PointerRequest> {
+        std::mem::take(&mut *self.pointer_requests.lock().unwrap())
+    }
+
+    /// Cancel a drag, releasing the button if it is held
+    fn cancel_drag(&self) {
+        let mut drag = self.drag.lock().unwrap();
+        if *drag == DragState::Holding {
+            self.request_pointer(PointerRequest::ReleaseButton);
+        }
+        *drag = DragState::Idle;
+    }
+
+    /// Release the keyboard grab, if any
+    pub fn release_keyboard(&self) {
+        if let Some(keyboard) = self.keyboard.lock().unwrap().as_mut() {
+            keyboard.ungrab();
+        }
+    }
+
+    /// Hide the overlay if it is visible, otherwise show it in `mode`
+    pub fn toggle(&self, mode: OverlayMode) {
+        if self.is_visible() {
+            self.hide();
+        } else {
+            self.show(mode);
+        }
+    }
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Collect hint targets on a worker, giving up after `timeout`
+///
+/// A worker that never answers is left behind; its result is dropped.
+fn collect_targets(
+    collect: fn() -> Result<Vec<HintTarget>>,
+    timeout: Duration,
+) -> Result<Vec<HintTarget>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(collect());
+    });
+
+    receiver.recv_timeout(timeout).map_err(|_| {
+        Error::Io(std::io::Error::new(
+            ErrorKind::TimedOut,
+            format!("no answer within {}s", timeout.as_secs()),
+        ))
+    })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn one_target() -> Result<Vec<HintTarget>> {
+        Ok(vec![HintTarget {
+            x: 10,
+            y: 20,
+            width: 30,
+            height: 40,
+            window: None,
+            title: String::new(),
+        }])
+    }
+
+    fn hanging() -> Result<Vec<HintTarget>> {
+        std::thread::sleep(Duration::from_secs(5));
+        one_target()
+    }
+
+    #[test]
+    fn targets_arrive_from_the_worker() {
+        let targets = collect_targets(one_target, Duration::from_secs(1)).unwrap();
+        assert_eq!(targets.len(), 1);
+    }
+
+    #[test]
+    fn hanging_collection_times_out() {
+        let started = Instant::now();
+        let result = collect_targets(hanging, Duration::from_millis(100));
+        assert!(matches!(result, Err(Error::Io(e)) if e.kind() == ErrorKind::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,188 @@
+//! Pointer backends: the ways mouse events reach the display server
+
+use crate::error::Result;
+use crate::system::wayland::{self, VirtualPointerBackend};
+use crate::system::x11::XTestBackend;
+use enigo::{Direction, Enigo, Mouse, Settings};
+
+/// A mouse button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// Axis of a scroll
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollAxis {
+    Vertical,
+    Horizontal,
+}
+
+/// Sends mouse events
+pub trait PointerBackend {
+    /// Move the pointer to screen coordinates
+    fn move_to(&mut self, x: i32, y: i32) -> Result<()>;
+
+    /// Press `button` where the pointer is
+    fn press(&mut self, button: PointerButton) -> Result<()>;
+
+    /// Release `button` where the pointer is
+    fn release(&mut self, button: PointerButton) -> Result<()>;
+
+    /// Press and release `button` where the pointer is
+    fn click(&mut self, button: PointerButton) -> Result<()> {
+        self.press(button)?;
+        self.release(button)
+    }
+
+    /// Scroll by `length` wheel notches; positive lengths scroll down and right
+    fn scroll(&mut self, length: i32, axis: ScrollAxis) -> Result<()>;
+}
+
+/// Which pointer backend to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerBackendKind {
+    /// enigo, through whatever it supports on this system
+    Enigo,
+    /// The XTEST extension of the X server, directly
+    XTest,
+    /// The wlroots virtual pointer protocol of the Wayland compositor
+    Wayland,
+    /// Record events instead of sending them, for dry runs and tests
+    Recording,
+}
+
+impl PointerBackendKind {
+    /// Get a backend kind from its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "enigo" => Some(Self::Enigo),
+            "xtest" => Some(Self::XTest),
+            "wayland" => Some(Self::Wayland),
+            "record" => Some(Self::Recording),
+            _ => None,
+        }
+    }
+
+    /// Connect a backend of this kind for a screen of `screen_size` pixels
+    pub fn connect(self, screen_size: (i32, i32)) -> Result<Box<dyn PointerBackend>> {
+        Ok(match self {
+            Self::Enigo => Box::new(EnigoBackend::new()?),
+            Self::XTest => Box::new(XTestBackend::new()?),
+            Self::Wayland => Box::new(VirtualPointerBackend::new(screen_size.0, screen_size.1)?),
+            Self::Recording => Box::new(RecordingBackend::default()),
+        })
+    }
+}
+
+impl Default for PointerBackendKind {
+    /// Pick the backend of the running session
+    fn default() -> Self {
+        if wayland::is_wayland_session() {
+            Self::Wayland
+        } else {
+            Self::Enigo
+        }
+    }
+}
+
+/// Backend sending events through enigo
+pub struct EnigoBackend {
+    enigo: Enigo,
+}
+
+impl EnigoBackend {
+    /// Create a new enigo connection
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            enigo: Enigo::new(&Settings::default())?,
+        })
+    }
+
+    /// Send a button event
+    fn button(&mut self, button: PointerButton, direction: Direction) -> Result<()> {
+        let button = match button {
+            PointerButton::Left => enigo::Button::Left,
+            PointerButton::Right => enigo::Button::Right,
+            PointerButton::Middle => enigo::Button::Middle,
+        };
+        self.enigo.button(button, direction)?;
+        Ok(())
+    }
+}
+
+impl PointerBackend for EnigoBackend {
+    fn move_to(&mut self, x: i32, y: i32) -> Result<()> {
+        self.enigo.move_mouse(x, y, enigo::Coordinate::Abs)?;
+        Ok(())
+    }
+
+    fn press(&mut self, button: PointerButton) -> Result<()> {
+        self.button(button, Direction::Press)
+    }
+
+    fn release(&mut self, button: PointerButton) -> Result<()> {
+        self.button(button, Direction::Release)
+    }
+
+    fn click(&mut self, button: PointerButton) -> Result<()> {
+        self.button(button, Direction::Click)
+    }
+
+    fn scroll(&mut self, length: i32, axis: ScrollAxis) -> Result<()> {
+        let axis = match axis {
+            ScrollAxis::Vertical => enigo::Axis::Vertical,
+            ScrollAxis::Horizontal => enigo::Axis::Horizontal,
+        };
+        self.enigo.scroll(length, axis)?;
+        Ok(())
+    }
+}
+
+/// An event sent to the recording backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerEvent {
+    MoveTo(i32, i32),
+    Press(PointerButton),
+    Release(PointerButton),
+    Click(PointerButton),
+    Scroll(i32, ScrollAxis),
+}
+
+/// Backend keeping the events it is sent, in order, instead of sending them
+#[derive(Debug, Clone, Default)]
+pub struct RecordingBackend {
+    pub events: Vec<PointerEvent>,
+}
+
+impl RecordingBackend {
+    /// Record an event
+    fn record(&mut self, event: PointerEvent) -> Result<()> {
+        self.events.push(event);
+        Ok(())
+    }
+}
+
+impl PointerBackend for RecordingBackend {
+    fn move_to(&mut self, x: i32, y: i32) -> Result<()> {
+        self.record(PointerEvent::MoveTo(x, y))
+    }
+
+    fn press(&mut self, button: PointerButton) -> Result<()> {
+        self.record(PointerEvent::Press(button))
+    }
+
+    fn release(&mut self, button: PointerButton) -> Result<()> {
+        self.record(PointerEvent::Release(button))
+    }
+
+    fn click(&mut self, button: PointerButton) -> Result<()> {
+        self.record(PointerEvent::Click(button))
+    }
+
+    fn scroll(&mut self, length: i32, axis: ScrollAxis) -> Result<()> {
+        self.record(PointerEvent::Scroll(length, axis))
+    }
+}
@@ -1,4 +1,5 @@
 mod app;
+mod cli;
 mod config;
 mod error;
 mod input;
@@ -6,22 +7,91 @@ mod models;
 mod system;
 mod ui;
 
+use std::process::ExitCode;
+
 use app::KmouseApp;
+use clap::Parser;
+use cli::{Cli, Command};
 use config::AppConfig;
 
-fn main() -> eframe::Result<()> {
+fn main() -> ExitCode {
     // Initialize logging
     env_logger::init();
 
-    // Load configuration
-    let config = match AppConfig::load() {
+    let cli = Cli::parse();
+
+    // Validate a config file without touching the display
+    if let Some(Command::CheckConfig { path }) = &cli.command {
+        return check_config(path.clone());
+    }
+
+    // Control a running instance
+    if let Some(Command::Msg { command }) = &cli.command {
+        return send_message(&command.join(" "));
+    }
+
+    // Load configuration, then apply command-line overrides
+    let config = AppConfig::load().and_then(|mut config| {
+        config.apply(cli.overrides.clone())?;
+        Ok(config)
+    });
+    let config = match config {
         Ok(config) => config,
         Err(e) => {
             eprintln!("Failed to load configuration: {}", e);
-            AppConfig::default()
+            return ExitCode::FAILURE;
         }
     };
 
+    if let Some(Command::PrintConfig) = &cli.command {
+        println!("{:#?}", config);
+        return ExitCode::SUCCESS;
+    }
+
     // Run the application
-    KmouseApp::run(config)
+    match KmouseApp::run(config, cli.overrides, cli.once) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Failed to run Kmouse: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Check a config file, the user config file by default
+fn check_config(path: Option<std::path::PathBuf>) -> ExitCode {
+    let Some(path) = path.or_else(config::config_path) else {
+        eprintln!("No config file path: neither XDG_CONFIG_HOME nor HOME is set");
+        return ExitCode::FAILURE;
+    };
+    if !path.exists() {
+        eprintln!("{} does not exist", path.display());
+        return ExitCode::FAILURE;
+    }
+
+    match AppConfig::default().apply_file(&path) {
+        Ok(()) => {
+            println!("{} is valid", path.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Send a command to the running instance and print its reply
+fn send_message(command: &str) -> ExitCode {
+    match input::ipc::send_command(command) {
+        Ok(reply) if reply == "ok" => ExitCode::SUCCESS,
+        Ok(reply) => {
+            eprintln!("{}", reply);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -1,11 +1,11 @@
 //! Cell data structures for the grid
 
-/// A cell with two characters (for the main grid)
+use crate::input::mouse::MouseAction;
+
+/// A cell with a multi-character label (for the main grid)
 #[derive(Debug, Clone)]
 pub struct CellPlural {
     pub combo: String,
-    pub first: char,
-    pub last: char,
 }
 
 impl CellPlural {
@@ -13,17 +13,13 @@ impl CellPlural {
     pub fn new() -> Self {
         Self {
             combo: String::new(),
-            first: char::default(),
-            last: char::default(),
         }
     }
 
     /// Create a cell with the given characters
-    pub fn with_chars(first: char, last: char) -> Self {
+    pub fn with_chars(chars: &[char]) -> Self {
         Self {
-            combo: format!("{}{}", first, last),
-            first,
-            last,
+            combo: chars.iter().collect(),
         }
     }
 }
@@ -35,10 +31,15 @@ pub struct CellSingular {
 }
 
 /// Represents the currently focused cell
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct FocusedCell {
-    pub first: char,
-    pub last: char,
+    pub monitor: Option<usize>,
+    /// Label characters typed so far
+    pub typed: String,
+    /// Micro grid keys chosen so far in recursive mode
+    pub zoom: Vec<char>,
+    /// Pointer placed in confirm mode, waiting for Enter or Space
+    pub pending: Option<PendingClick>,
     pub conclusion: char,
 }
 
@@ -46,20 +47,22 @@ impl FocusedCell {
     /// Create a new empty focused cell
     pub fn new() -> Self {
         Self {
-            first: char::default(),
-            last: char::default(),
+            monitor: None,
+            typed: String::new(),
+            zoom: Vec::new(),
+            pending: None,
             conclusion: char::default(),
         }
     }
 
-    /// Check if the first character is selected
-    pub fn has_first(&self) -> bool {
-        self.first != char::default()
+    /// Check if a monitor is selected
+    pub fn has_monitor(&self) -> bool {
+        self.monitor.is_some()
     }
 
-    /// Check if the last character is selected
-    pub fn has_last(&self) -> bool {
-        self.last != char::default()
+    /// Check if a cell label starts with the characters typed so far
+    pub fn matches(&self, combo: &str) -> bool {
+        combo.starts_with(&self.typed)
     }
 
     /// Check if the conclusion character is selected
@@ -67,9 +70,9 @@ impl FocusedCell {
         self.conclusion != char::default()
     }
 
-    /// Check if this cell is fully selected
-    pub fn is_complete(&self) -> bool {
-        self.has_first() && self.has_last()
+    /// Check if a whole label of `label_len` characters was typed
+    pub fn is_complete(&self, label_len: usize) -> bool {
+        self.typed.chars().count() >= label_len
     }
 
     /// Reset the cell
@@ -83,3 +86,49 @@ impl Default for FocusedCell {
         Self::new()
     }
 }
+
+/// A mouse action waiting for confirmation at screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingClick {
+    pub x: i32,
+    pub y: i32,
+    pub action: MouseAction,
+}
+
+/// Progress of a drag-and-drop selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DragState {
+    /// No drag in progress
+    #[default]
+    Idle,
+    /// The next selection presses the button
+    Armed,
+    /// The button is held; the next selection releases it
+    Holding,
+}
+
+impl DragState {
+    /// Check if a drag is armed or in progress
+    pub fn is_active(&self) -> bool {
+        *self != Self::Idle
+    }
+}
+
+/// Progress of a keyboard scroll selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScrollState {
+    /// No scrolling in progress
+    #[default]
+    Idle,
+    /// The next selection places the cursor to scroll under
+    Armed,
+    /// Scroll keys are sent under the cursor
+    Scrolling,
+}
+
+impl ScrollState {
+    /// Check if scroll mode is armed or in progress
+    pub fn is_active(&self) -> bool {
+        *self != Self::Idle
+    }
+}
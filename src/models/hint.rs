@@ -0,0 +1,42 @@
+//! Hint target data structures
+
+/// An element that can be picked by its hint label, in root window pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HintTarget {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Top-level X window, for window hints
+    pub window: Option<u32>,
+    /// Title shown next to the label, empty for none
+    pub title: String,
+}
+
+impl HintTarget {
+    /// Get the center of the element
+    pub fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+}
+
+/// What picking a window hint does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WindowAction {
+    /// Ask the window manager to focus the window
+    #[default]
+    Activate,
+    /// Click the center of the window
+    Click,
+}
+
+impl WindowAction {
+    /// Get a window action from its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "activate" => Some(Self::Activate),
+            "click" => Some(Self::Click),
+            _ => None,
+        }
+    }
+}
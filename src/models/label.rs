@@ -0,0 +1,187 @@
+//! Label alphabets for the grid
+
+use crate::error::{Error, Result};
+use crate::input::keyboard;
+
+/// Number of keys in the 4x4 micro grid
+pub const MICRO_GRID_SIZE: usize = 16;
+
+/// Order in which labels are assigned to grid cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LabelOrder {
+    /// Labels run row by row from the top left cell
+    #[default]
+    Sequential,
+    /// The easiest labels go to the cells closest to the center
+    Center,
+}
+
+impl LabelOrder {
+    /// Get a label order from its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sequential" => Some(Self::Sequential),
+            "center" => Some(Self::Center),
+            _ => None,
+        }
+    }
+}
+
+/// Alphabets used to label the grid and the micro grid
+///
+/// Earlier characters are considered easier to type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabets {
+    pub hint: Vec<char>,
+    pub micro: Vec<char>,
+    pub order: LabelOrder,
+}
+
+impl Alphabets {
+    /// Get the alphabets for a keyboard layout, home row first
+    pub fn for_layout(name: &str) -> Option<Self> {
+        let (hint, micro) = match name {
+            "alphabetical" => ("ABCDEFGHIJKLMNOPQRSTUVWXYZ", "QWERASDFUOIPJKL;"),
+            "qwerty" => ("ASDFJKLGHQWERUIOPTYZXCVBNM", "QWERASDFUOIPJKL;"),
+            "dvorak" => ("AOEUHTNSIDPYFGCRLQJKXBMWVZ", "',.PAOEUGRCLHTNS"),
+            "colemak" => ("ARSTNEIODHQWFPLUYGJZXCVBKM", "QWFPARSTLYU;NEIO"),
+            _ => return None,
+        };
+
+        Some(Self {
+            hint: hint.chars().collect(),
+            micro: micro.chars().collect(),
+            order: LabelOrder::default(),
+        })
+    }
+
+    /// Parse an alphabet from the config, normalizing letters to upper case
+    pub fn parse(alphabet: &str) -> Vec<char> {
+        alphabet.chars().map(|c| c.to_ascii_uppercase()).collect()
+    }
+
+    /// Check that both alphabets are usable
+    pub fn validate(&self) -> Result<()> {
+        validate_alphabet("hint_alphabet", &self.hint)?;
+        if self.hint.len() < 2 {
+            return Err(Error::Config(format!(
+                "hint_alphabet needs at least 2 characters, got {}",
+                self.hint.len()
+            )));
+        }
+
+        validate_alphabet("micro_alphabet", &self.micro)?;
+        if self.micro.len() != MICRO_GRID_SIZE {
+            return Err(Error::Config(format!(
+                "micro_alphabet needs exactly {} characters, got {}",
+                MICRO_GRID_SIZE,
+                self.micro.len()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Alphabets {
+    fn default() -> Self {
+        Self::for_layout("alphabetical").expect("alphabetical layout exists")
+    }
+}
+
+/// Reject duplicate characters and characters without a key
+fn validate_alphabet(name: &str, alphabet: &[char]) -> Result<()> {
+    for (index, &c) in alphabet.iter().enumerate() {
+        if alphabet[..index].contains(&c) {
+            return Err(Error::Config(format!(
+                "{} contains '{}' more than once",
+                name, c
+            )));
+        }
+        if keyboard::key_from_char(c).is_none() {
+            return Err(Error::Config(format!(
+                "{} contains '{}', which has no key",
+                name, c
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alphabets from config strings
+    fn alphabets(hint: &str, micro: &str) -> Alphabets {
+        Alphabets {
+            hint: Alphabets::parse(hint),
+            micro: Alphabets::parse(micro),
+            order: LabelOrder::default(),
+        }
+    }
+
+    #[test]
+    fn every_layout_is_valid() {
+        for name in ["alphabetical", "qwerty", "dvorak", "colemak"] {
+            let alphabets = Alphabets::for_layout(name).unwrap();
+            assert_eq!(alphabets.validate().ok(), Some(()), "{}", name);
+        }
+        assert_eq!(Alphabets::for_layout("azerty"), None);
+    }
+
+    #[test]
+    fn parse_upper_cases_letters() {
+        assert_eq!(Alphabets::parse("asdf;"), ['A', 'S', 'D', 'F', ';']);
+    }
+
+    #[test]
+    fn duplicate_letters_are_rejected() {
+        for (hint, micro) in [
+            ("ABCA", "QWERASDFUOIPJKL;"),
+            ("ABCa", "QWERASDFUOIPJKL;"),
+            ("ABCD", "QWERASDFUOIPJKLQ"),
+        ] {
+            let result = alphabets(hint, micro).validate();
+            assert!(
+                matches!(&result, Err(Error::Config(message)) if message.contains("more than once")),
+                "{} {}: {:?}",
+                hint,
+                micro,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn letters_without_a_key_are_rejected() {
+        let result = alphabets("ABé", "QWERASDFUOIPJKL;").validate();
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn grid_and_micro_alphabets_may_overlap() {
+        // The micro keys are only read once a grid label is complete
+        assert!(alphabets("QWER", "QWERASDFUOIPJKL;").validate().is_ok());
+    }
+
+    #[test]
+    fn alphabets_too_short_are_rejected() {
+        // A single letter can't tell grid cells apart
+        assert!(matches!(
+            alphabets("A", "QWERASDFUOIPJKL;").validate(),
+            Err(Error::Config(_))
+        ));
+        assert!(alphabets("AB", "QWERASDFUOIPJKL;").validate().is_ok());
+
+        // The micro grid needs one key per cell
+        for micro in ["QWERASDFUOIPJKL", "QWERASDFUOIPJKL;Z", ""] {
+            assert!(
+                matches!(alphabets("AB", micro).validate(), Err(Error::Config(_))),
+                "{:?}",
+                micro
+            );
+        }
+    }
+}
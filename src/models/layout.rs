@@ -0,0 +1,201 @@
+//! Grid geometry
+
+use eframe::egui::{pos2, vec2, Pos2, Rect};
+
+/// Columns of the micro grid
+pub const MICRO_COLS: usize = 4;
+
+/// Rows of the micro grid
+pub const MICRO_ROWS: usize = 4;
+
+/// Layout of the main grid over an area of the window
+///
+/// Rects are in window points; screen coordinates are in root window pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridLayout {
+    /// Area covered by the grid
+    pub area: Rect,
+    pub cols: usize,
+    pub rows: usize,
+    pub cell_width: f32,
+    pub cell_height: f32,
+    /// Screen position of the window origin, in pixels
+    pub origin: (i32, i32),
+    pub pixels_per_point: f32,
+}
+
+impl GridLayout {
+    /// Fit cells of about `cell_size` points into `area`
+    pub fn new(area: Rect, cell_size: f32, origin: (i32, i32), pixels_per_point: f32) -> Self {
+        let cols = (area.width() / cell_size).floor().max(1.0) as usize;
+        let rows = (area.height() / cell_size).floor().max(1.0) as usize;
+
+        Self {
+            area,
+            cols,
+            rows,
+            cell_width: area.width() / cols as f32,
+            cell_height: area.height() / rows as f32,
+            origin,
+            pixels_per_point,
+        }
+    }
+
+    /// Get the number of cells
+    pub fn cell_count(&self) -> usize {
+        self.cols * self.rows
+    }
+
+    /// Get the rect of the cell at `index`, counting row by row
+    pub fn cell_rect(&self, index: usize) -> Rect {
+        let row = index / self.cols;
+        let col = index % self.cols;
+
+        Rect::from_min_size(
+            self.area.min + vec2(col as f32 * self.cell_width, row as f32 * self.cell_height),
+            vec2(self.cell_width, self.cell_height),
+        )
+    }
+
+    /// Narrow `cell` down to the micro grid level picked by `zoom`
+    pub fn zoom_rect(cell: Rect, keys: &[char], zoom: &[char]) -> Rect {
+        zoom.iter()
+            .filter_map(|unit| keys.iter().position(|c| c == unit))
+            .fold(cell, micro_cell_rect)
+    }
+
+    /// Convert a window position to screen coordinates
+    pub fn to_screen(self, pos: Pos2) -> (i32, i32) {
+        (
+            self.origin.0 + (pos.x * self.pixels_per_point).round() as i32,
+            self.origin.1 + (pos.y * self.pixels_per_point).round() as i32,
+        )
+    }
+
+    /// Convert screen coordinates to a window position
+    pub fn to_window(self, x: i32, y: i32) -> Pos2 {
+        pos2(
+            (x - self.origin.0) as f32 / self.pixels_per_point,
+            (y - self.origin.1) as f32 / self.pixels_per_point,
+        )
+    }
+}
+
+/// Get the rect of the micro grid cell at `index` inside `parent`
+pub fn micro_cell_rect(parent: Rect, index: usize) -> Rect {
+    let cell_size = vec2(
+        parent.width() / MICRO_COLS as f32,
+        parent.height() / MICRO_ROWS as f32,
+    );
+    let row = index / MICRO_COLS;
+    let col = index % MICRO_COLS;
+
+    Rect::from_min_size(
+        parent.min + vec2(col as f32 * cell_size.x, row as f32 * cell_size.y),
+        cell_size,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::selection::MAX_ZOOM_DEPTH;
+
+    /// Grid over a 1920x1080 pixel window at `origin`
+    fn full_hd(origin: (i32, i32), pixels_per_point: f32) -> GridLayout {
+        let area = Rect::from_min_size(Pos2::ZERO, vec2(1920.0, 1080.0) / pixels_per_point);
+        GridLayout::new(area, 64.0, origin, pixels_per_point)
+    }
+
+    #[test]
+    fn screen_positions_round_trip() {
+        // No dock, a dock on the left, a panel on top
+        for origin in [(0, 0), (48, 0), (0, 32)] {
+            for pixels_per_point in [1.0, 1.5, 2.0] {
+                let layout = full_hd(origin, pixels_per_point);
+                assert_eq!(layout.to_screen(Pos2::ZERO), origin);
+
+                for (x, y) in [(0, 0), (1, 1), (301, 202), (1919, 1079)] {
+                    let screen = (origin.0 + x, origin.1 + y);
+                    assert_eq!(
+                        layout.to_screen(layout.to_window(screen.0, screen.1)),
+                        screen,
+                        "origin {:?} at {} pixels per point",
+                        origin,
+                        pixels_per_point
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn window_positions_scale_with_pixels_per_point() {
+        let layout = full_hd((0, 32), 2.0);
+        assert_eq!(layout.to_screen(pos2(10.0, 10.0)), (20, 52));
+        assert_eq!(layout.to_window(20, 52), pos2(10.0, 10.0));
+
+        let layout = full_hd((48, 0), 1.5);
+        assert_eq!(layout.to_screen(pos2(100.0, 100.0)), (198, 150));
+        assert_eq!(layout.to_window(198, 150), pos2(100.0, 100.0));
+    }
+
+    #[test]
+    fn last_cells_reach_the_area_edges() {
+        let layout = full_hd((0, 0), 1.0);
+        assert_eq!((layout.cols, layout.rows), (30, 16));
+
+        // Last column of the first row
+        let rect = layout.cell_rect(layout.cols - 1);
+        assert_eq!(rect.right(), layout.area.right());
+        assert_eq!(rect.top(), layout.area.top());
+
+        // First column of the last row
+        let rect = layout.cell_rect((layout.rows - 1) * layout.cols);
+        assert_eq!(rect.left(), layout.area.left());
+        assert_eq!(rect.bottom(), layout.area.bottom());
+
+        // Last cell
+        let rect = layout.cell_rect(layout.cell_count() - 1);
+        assert_eq!(rect.max, layout.area.max);
+        assert_eq!(rect.size(), vec2(64.0, 67.5));
+    }
+
+    #[test]
+    fn zoom_rect_picks_a_micro_cell() {
+        let keys: Vec<char> = "QWERASDFUOIPJKL;".chars().collect();
+        let cell = Rect::from_min_size(pos2(64.0, 0.0), vec2(64.0, 64.0));
+
+        assert_eq!(GridLayout::zoom_rect(cell, &keys, &[]), cell);
+        assert_eq!(
+            GridLayout::zoom_rect(cell, &keys, &['Q']),
+            Rect::from_min_size(pos2(64.0, 0.0), vec2(16.0, 16.0))
+        );
+        assert_eq!(
+            GridLayout::zoom_rect(cell, &keys, &['F']),
+            Rect::from_min_size(pos2(112.0, 16.0), vec2(16.0, 16.0))
+        );
+
+        // Keys outside the micro alphabet are skipped
+        assert_eq!(
+            GridLayout::zoom_rect(cell, &keys, &['Z', 'F']),
+            GridLayout::zoom_rect(cell, &keys, &['F'])
+        );
+    }
+
+    #[test]
+    fn zoom_rect_at_max_depth() {
+        let keys: Vec<char> = "QWERASDFUOIPJKL;".chars().collect();
+        let cell = Rect::from_min_size(pos2(64.0, 0.0), vec2(64.0, 64.0));
+        let size = 64.0 / 4f32.powi(MAX_ZOOM_DEPTH as i32);
+
+        let first = GridLayout::zoom_rect(cell, &keys, &['Q'; MAX_ZOOM_DEPTH]);
+        assert_eq!(first, Rect::from_min_size(cell.min, vec2(size, size)));
+
+        let last = GridLayout::zoom_rect(cell, &keys, &[';'; MAX_ZOOM_DEPTH]);
+        assert_eq!(
+            last,
+            Rect::from_min_max(cell.max - vec2(size, size), cell.max)
+        );
+    }
+}
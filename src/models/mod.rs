@@ -1,4 +1,8 @@
 //! Data models for the Kmouse application
 
 pub mod cell;
-pub mod margin;
+pub mod hint;
+pub mod label;
+pub mod layout;
+pub mod monitor;
+pub mod selection;
@@ -0,0 +1,54 @@
+//! Monitor data structures
+
+use eframe::egui::{pos2, vec2, Rect};
+
+/// A monitor rectangle in root window pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Monitor {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Monitor {
+    /// Create a monitor covering the whole screen
+    pub fn whole_screen(width: i32, height: i32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    /// Get the part of this rectangle inside `other`, if any
+    pub fn intersect(&self, other: &Monitor) -> Option<Monitor> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+
+        (right > x && bottom > y).then_some(Monitor {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        })
+    }
+
+    /// Convert to an egui rect in the points of a window whose origin is at
+    /// `origin` on the screen
+    pub fn to_egui_rect(self, origin: (i32, i32), pixels_per_point: f32) -> Rect {
+        Rect::from_min_size(
+            pos2(
+                (self.x - origin.0) as f32 / pixels_per_point,
+                (self.y - origin.1) as f32 / pixels_per_point,
+            ),
+            vec2(
+                self.width as f32 / pixels_per_point,
+                self.height as f32 / pixels_per_point,
+            ),
+        )
+    }
+}
@@ -0,0 +1,621 @@
+//! Grid selection state machine
+//!
+//! The selection consumes key events and tells the UI which mouse actions to
+//! perform; it never touches the pointer or the screen itself.
+
+use eframe::egui::Modifiers;
+
+use crate::input::mouse::MouseAction;
+use crate::models::cell::{CellPlural, DragState, FocusedCell, PendingClick, ScrollState};
+use crate::models::label::Alphabets;
+use crate::models::layout::{self, GridLayout};
+
+/// Deepest zoom level in recursive mode; keys at this level click directly
+pub const MAX_ZOOM_DEPTH: usize = 6;
+
+/// A key event fed to the selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionKey {
+    /// A character key went down
+    Pressed(char),
+    /// A character key went up
+    Released(char),
+    Enter,
+    Space,
+    Backspace,
+    /// An arrow key, as a pixel offset
+    Arrow(i32, i32),
+    /// The configured exit key
+    Exit,
+    /// The configured drag key
+    Drag,
+    /// The configured scroll key
+    Scroll,
+}
+
+/// What the UI has to do after a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionAction {
+    /// Move the pointer without clicking
+    MoveTo(i32, i32),
+    /// Perform a mouse action, then call `Selection::finish` with `unit`
+    Perform {
+        x: i32,
+        y: i32,
+        action: MouseAction,
+        /// Micro grid key that picked the point, if one was used
+        unit: Option<char>,
+    },
+    /// Release the held mouse button
+    ReleaseButton,
+}
+
+/// What the UI has to do after a key in hint mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintAction {
+    /// Pick the target with this label index
+    Pick(usize),
+    /// Leave hint mode for the grid
+    Leave,
+}
+
+/// Settings and grid a selection is made on
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionRules<'a> {
+    pub layout: &'a GridLayout,
+    pub cells: &'a [CellPlural],
+    pub alphabets: &'a Alphabets,
+    pub click_action: MouseAction,
+    pub recursive: bool,
+    pub confirm: bool,
+}
+
+impl SelectionRules<'_> {
+    /// Get the length of every cell label
+    pub fn label_len(&self) -> usize {
+        self.cells
+            .first()
+            .map_or(0, |cell| cell.combo.chars().count())
+    }
+}
+
+/// A grid selection, driving the overlay state
+pub struct Selection<'a> {
+    pub focused_cell: &'a mut FocusedCell,
+    pub drag: &'a mut DragState,
+    pub scroll: &'a mut ScrollState,
+    pub move_only: bool,
+}
+
+impl Selection<'_> {
+    /// Handle a key, returning the mouse action it calls for, if any
+    ///
+    /// Label letters are taken on release and micro grid keys on press, so
+    /// the last letter of a label does not also pick a micro cell.
+    pub fn handle(
+        &mut self,
+        key: SelectionKey,
+        modifiers: &Modifiers,
+        rules: &SelectionRules,
+    ) -> Option<SelectionAction> {
+        match key {
+            // Reset the focused cell and cancel drag or scroll mode
+            SelectionKey::Exit => {
+                let holding = *self.drag == DragState::Holding;
+                *self.drag = DragState::Idle;
+                *self.scroll = ScrollState::Idle;
+                self.focused_cell.reset();
+                return holding.then_some(SelectionAction::ReleaseButton);
+            }
+            SelectionKey::Drag => {
+                if !self.scroll.is_active() {
+                    *self.drag = match *self.drag {
+                        DragState::Idle => DragState::Armed,
+                        DragState::Armed => DragState::Idle,
+                        DragState::Holding => DragState::Holding,
+                    };
+                }
+                return None;
+            }
+            SelectionKey::Scroll => {
+                if !self.drag.is_active() {
+                    *self.scroll = match *self.scroll {
+                        ScrollState::Idle => ScrollState::Armed,
+                        ScrollState::Armed => ScrollState::Idle,
+                        ScrollState::Scrolling => ScrollState::Scrolling,
+                    };
+                }
+                return None;
+            }
+            _ => {}
+        }
+
+        // Nudge and confirm a placed pointer instead of selecting
+        if self.focused_cell.pending.is_some() {
+            return self.handle_confirm(key, modifiers);
+        }
+
+        // Wait for the final key of the previous selection to be released
+        if self.focused_cell.has_conclusion() {
+            if key == SelectionKey::Released(self.focused_cell.conclusion) {
+                self.focused_cell.conclusion = char::default();
+            }
+            return None;
+        }
+
+        if !self.focused_cell.is_complete(rules.label_len()) {
+            self.type_label(key, rules.cells, rules.alphabets);
+            return None;
+        }
+
+        self.handle_micro(key, modifiers, rules)
+    }
+
+    /// Handle a key in hint mode, where `labels` name the hint targets
+    ///
+    /// Letters are typed as on the grid; exit clears them, or leaves hint
+    /// mode when nothing was typed.
+    pub fn handle_hint(
+        &mut self,
+        key: SelectionKey,
+        labels: &[CellPlural],
+        alphabets: &Alphabets,
+    ) -> Option<HintAction> {
+        if key == SelectionKey::Exit {
+            let leave = self.focused_cell.typed.is_empty();
+            self.focused_cell.reset();
+            return leave.then_some(HintAction::Leave);
+        }
+
+        self.type_label(key, labels, alphabets);
+        labels
+            .iter()
+            .position(|label| label.combo == self.focused_cell.typed)
+            .map(HintAction::Pick)
+    }
+
+    /// Type a label letter on release, or undo the last one on Backspace
+    ///
+    /// Only letters that still lead to a label are accepted.
+    fn type_label(&mut self, key: SelectionKey, labels: &[CellPlural], alphabets: &Alphabets) {
+        match key {
+            SelectionKey::Released(c) if alphabets.hint.contains(&c) => {
+                let typed = format!("{}{}", self.focused_cell.typed, c);
+                if labels.iter().any(|label| label.combo.starts_with(&typed)) {
+                    self.focused_cell.typed = typed;
+                }
+            }
+            SelectionKey::Backspace => {
+                self.focused_cell.typed.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Finish a selection once its mouse action was performed
+    ///
+    /// Returns false when the overlay should hide.
+    pub fn finish(&mut self, unit: Option<char>) -> bool {
+        self.focused_cell.reset();
+
+        // Keep the overlay up to pick the drop target
+        if *self.drag == DragState::Armed {
+            *self.drag = DragState::Holding;
+            self.focused_cell.conclusion = unit.unwrap_or_default();
+            return true;
+        }
+
+        // Keep the overlay up to take scroll keys
+        if *self.scroll == ScrollState::Armed {
+            *self.scroll = ScrollState::Scrolling;
+            return true;
+        }
+
+        *self.drag = DragState::Idle;
+        false
+    }
+
+    /// Get the action drag, scroll and move-only modes force on selections
+    pub fn forced_action(&self) -> Option<MouseAction> {
+        match (*self.drag, *self.scroll) {
+            (_, ScrollState::Armed) => Some(MouseAction::Move),
+            (DragState::Armed, _) => Some(MouseAction::Press),
+            (DragState::Holding, _) => Some(MouseAction::Release),
+            _ if self.move_only => Some(MouseAction::Move),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the mode the next selection is made in, if not clicking
+    pub fn mode_label(&self) -> Option<&'static str> {
+        match (*self.drag, *self.scroll) {
+            (DragState::Armed, _) => Some("DRAG"),
+            (DragState::Holding, _) => Some("DROP"),
+            (_, ScrollState::Armed) => Some("SCROLL"),
+            _ if self.move_only => Some("MOVE"),
+            _ => None,
+        }
+    }
+
+    /// Get the rect of the cell whose whole label was typed
+    pub fn selected_cell(&self, rules: &SelectionRules) -> Option<eframe::egui::Rect> {
+        rules
+            .cells
+            .iter()
+            .position(|cell| cell.combo == self.focused_cell.typed)
+            .map(|index| rules.layout.cell_rect(index))
+    }
+
+    /// Handle a key in the micro grid of the selected cell
+    ///
+    /// In recursive mode each key zooms into its sub-cell and Enter clicks the
+    /// center of the current level; Backspace goes up one level.
+    fn handle_micro(
+        &mut self,
+        key: SelectionKey,
+        modifiers: &Modifiers,
+        rules: &SelectionRules,
+    ) -> Option<SelectionAction> {
+        let cell = self.selected_cell(rules)?;
+        let keys = &rules.alphabets.micro;
+        let level = GridLayout::zoom_rect(cell, keys, &self.focused_cell.zoom);
+
+        // A forced action wins; otherwise modifiers pick it
+        let action = self
+            .forced_action()
+            .unwrap_or_else(|| MouseAction::from_modifiers(modifiers, rules.click_action));
+
+        match key {
+            SelectionKey::Enter if rules.recursive => {
+                let (x, y) = rules.layout.to_screen(level.center());
+                Some(self.perform(x, y, action, None, rules.confirm))
+            }
+            SelectionKey::Backspace => {
+                // Above the first zoom level, undo the last letter
+                if self.focused_cell.zoom.pop().is_none() {
+                    self.focused_cell.typed.pop();
+                }
+                None
+            }
+            SelectionKey::Pressed(unit) => {
+                let index = keys.iter().position(|&c| c == unit)?;
+                if rules.recursive && self.focused_cell.zoom.len() < MAX_ZOOM_DEPTH {
+                    self.focused_cell.zoom.push(unit);
+                    return None;
+                }
+
+                let (x, y) = rules
+                    .layout
+                    .to_screen(layout::micro_cell_rect(level, index).center());
+                Some(self.perform(x, y, action, Some(unit), rules.confirm))
+            }
+            _ => None,
+        }
+    }
+
+    /// Perform `action` at the given screen coordinates
+    ///
+    /// In confirm mode the pointer is only moved there, and the action is kept
+    /// for the confirm stage.
+    fn perform(
+        &mut self,
+        x: i32,
+        y: i32,
+        action: MouseAction,
+        unit: Option<char>,
+        confirm: bool,
+    ) -> SelectionAction {
+        if confirm {
+            self.focused_cell.pending = Some(PendingClick { x, y, action });
+            SelectionAction::MoveTo(x, y)
+        } else {
+            SelectionAction::Perform { x, y, action, unit }
+        }
+    }
+
+    /// Handle a key in the confirm stage of a placed pointer
+    ///
+    /// hjkl and the arrow keys nudge the pointer by 1 pixel, 5 with Shift and
+    /// 20 with Ctrl; Enter or Space performs the pending action.
+    fn handle_confirm(
+        &mut self,
+        key: SelectionKey,
+        modifiers: &Modifiers,
+    ) -> Option<SelectionAction> {
+        let pending = self.focused_cell.pending.as_mut()?;
+
+        let (dx, dy) = match key {
+            SelectionKey::Arrow(dx, dy) => (dx, dy),
+            SelectionKey::Pressed('H') => (-1, 0),
+            SelectionKey::Pressed('J') => (0, 1),
+            SelectionKey::Pressed('K') => (0, -1),
+            SelectionKey::Pressed('L') => (1, 0),
+            SelectionKey::Enter | SelectionKey::Space => {
+                return Some(SelectionAction::Perform {
+                    x: pending.x,
+                    y: pending.y,
+                    action: pending.action,
+                    unit: None,
+                });
+            }
+            _ => return None,
+        };
+
+        let step = if modifiers.ctrl {
+            20
+        } else if modifiers.shift {
+            5
+        } else {
+            1
+        };
+        pending.x += dx * step;
+        pending.y += dy * step;
+        Some(SelectionAction::MoveTo(pending.x, pending.y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui::{pos2, vec2, Rect};
+
+    /// A 4x2 grid of 64 point cells labelled A to H, below a 32 pixel panel
+    fn grid() -> (GridLayout, Vec<CellPlural>, Alphabets) {
+        let area = Rect::from_min_size(pos2(0.0, 0.0), vec2(256.0, 128.0));
+        let layout = GridLayout::new(area, 64.0, (0, 32), 1.0);
+        let cells = ('A'..='H').map(|c| CellPlural::with_chars(&[c])).collect();
+        let alphabets = Alphabets::for_layout("alphabetical").unwrap();
+        (layout, cells, alphabets)
+    }
+
+    /// Cells labelled with `combos`
+    fn labels(combos: &[&str]) -> Vec<CellPlural> {
+        combos
+            .iter()
+            .map(|combo| CellPlural::with_chars(&combo.chars().collect::<Vec<_>>()))
+            .collect()
+    }
+
+    fn rules<'a>(
+        layout: &'a GridLayout,
+        cells: &'a [CellPlural],
+        alphabets: &'a Alphabets,
+    ) -> SelectionRules<'a> {
+        SelectionRules {
+            layout,
+            cells,
+            alphabets,
+            click_action: MouseAction::LeftClick,
+            recursive: false,
+            confirm: false,
+        }
+    }
+
+    /// Overlay state a selection works on
+    #[derive(Default)]
+    struct State {
+        focused_cell: FocusedCell,
+        drag: DragState,
+        scroll: ScrollState,
+    }
+
+    impl State {
+        fn selection(&mut self) -> Selection<'_> {
+            Selection {
+                focused_cell: &mut self.focused_cell,
+                drag: &mut self.drag,
+                scroll: &mut self.scroll,
+                move_only: false,
+            }
+        }
+    }
+
+    #[test]
+    fn exit_while_holding_releases_the_button() {
+        let (layout, cells, alphabets) = grid();
+        let rules = rules(&layout, &cells, &alphabets);
+        let mut state = State {
+            drag: DragState::Holding,
+            ..State::default()
+        };
+
+        let action = state
+            .selection()
+            .handle(SelectionKey::Exit, &Modifiers::NONE, &rules);
+        assert_eq!(action, Some(SelectionAction::ReleaseButton));
+        assert_eq!(state.drag, DragState::Idle);
+
+        // Nothing is held once armed only
+        state.drag = DragState::Armed;
+        let action = state
+            .selection()
+            .handle(SelectionKey::Exit, &Modifiers::NONE, &rules);
+        assert_eq!(action, None);
+        assert_eq!(state.drag, DragState::Idle);
+    }
+
+    #[test]
+    fn letters_count_on_release() {
+        let (layout, cells, alphabets) = grid();
+        let rules = rules(&layout, &cells, &alphabets);
+        let mut state = State::default();
+        let mut selection = state.selection();
+
+        let none = Modifiers::NONE;
+        assert_eq!(
+            selection.handle(SelectionKey::Pressed('B'), &none, &rules),
+            None
+        );
+        assert_eq!(selection.focused_cell.typed, "");
+
+        // Letters without a cell are ignored
+        selection.handle(SelectionKey::Released('Z'), &none, &rules);
+        assert_eq!(selection.focused_cell.typed, "");
+
+        selection.handle(SelectionKey::Released('B'), &none, &rules);
+        assert_eq!(selection.focused_cell.typed, "B");
+
+        // Micro keys are taken on press; Q is the top left micro cell of B
+        assert_eq!(
+            selection.handle(SelectionKey::Pressed('Q'), &none, &rules),
+            Some(SelectionAction::Perform {
+                x: 72,
+                y: 40,
+                action: MouseAction::LeftClick,
+                unit: Some('Q'),
+            })
+        );
+    }
+
+    #[test]
+    fn confirm_stage_nudges_the_pointer() {
+        let (layout, cells, alphabets) = grid();
+        let rules = SelectionRules {
+            confirm: true,
+            ..rules(&layout, &cells, &alphabets)
+        };
+        let mut state = State::default();
+        let mut selection = state.selection();
+
+        let none = Modifiers::NONE;
+        selection.handle(SelectionKey::Released('B'), &none, &rules);
+        assert_eq!(
+            selection.handle(SelectionKey::Pressed('Q'), &none, &rules),
+            Some(SelectionAction::MoveTo(72, 40))
+        );
+
+        // 1 pixel, 5 with Shift, 20 with Ctrl
+        let nudges = [
+            (SelectionKey::Arrow(1, 0), Modifiers::NONE, (73, 40)),
+            (SelectionKey::Pressed('J'), Modifiers::SHIFT, (73, 45)),
+            (SelectionKey::Arrow(-1, 0), Modifiers::CTRL, (53, 45)),
+            (SelectionKey::Pressed('K'), Modifiers::NONE, (53, 44)),
+        ];
+        for (key, modifiers, (x, y)) in nudges {
+            assert_eq!(
+                selection.handle(key, &modifiers, &rules),
+                Some(SelectionAction::MoveTo(x, y))
+            );
+        }
+
+        assert_eq!(
+            selection.handle(SelectionKey::Enter, &none, &rules),
+            Some(SelectionAction::Perform {
+                x: 53,
+                y: 44,
+                action: MouseAction::LeftClick,
+                unit: None,
+            })
+        );
+    }
+
+    #[test]
+    fn backspace_pops_zoom_then_typed() {
+        let (layout, cells, alphabets) = grid();
+        let rules = SelectionRules {
+            recursive: true,
+            ..rules(&layout, &cells, &alphabets)
+        };
+        let mut state = State::default();
+        let mut selection = state.selection();
+
+        let none = Modifiers::NONE;
+        selection.handle(SelectionKey::Released('B'), &none, &rules);
+        selection.handle(SelectionKey::Pressed('Q'), &none, &rules);
+        selection.handle(SelectionKey::Pressed('W'), &none, &rules);
+        assert_eq!(selection.focused_cell.zoom, ['Q', 'W']);
+
+        // Enter clicks the center of the current level
+        assert_eq!(
+            selection.handle(SelectionKey::Enter, &none, &rules),
+            Some(SelectionAction::Perform {
+                x: 70,
+                y: 34,
+                action: MouseAction::LeftClick,
+                unit: None,
+            })
+        );
+
+        selection.handle(SelectionKey::Backspace, &none, &rules);
+        assert_eq!(selection.focused_cell.zoom, ['Q']);
+        selection.handle(SelectionKey::Backspace, &none, &rules);
+        assert!(selection.focused_cell.zoom.is_empty());
+        assert_eq!(selection.focused_cell.typed, "B");
+        selection.handle(SelectionKey::Backspace, &none, &rules);
+        assert_eq!(selection.focused_cell.typed, "");
+    }
+
+    #[test]
+    fn backspace_undoes_label_letters() {
+        let (layout, _, alphabets) = grid();
+        let cells = labels(&["AA", "AB", "BA"]);
+        let rules = rules(&layout, &cells, &alphabets);
+        let mut state = State::default();
+        let mut selection = state.selection();
+
+        let none = Modifiers::NONE;
+        selection.handle(SelectionKey::Released('A'), &none, &rules);
+        selection.handle(SelectionKey::Backspace, &none, &rules);
+        assert_eq!(selection.focused_cell.typed, "");
+
+        // Without recursion, Backspace in the micro grid undoes a letter too
+        selection.handle(SelectionKey::Released('B'), &none, &rules);
+        selection.handle(SelectionKey::Released('A'), &none, &rules);
+        assert_eq!(selection.focused_cell.typed, "BA");
+        selection.handle(SelectionKey::Backspace, &none, &rules);
+        assert_eq!(selection.focused_cell.typed, "B");
+    }
+
+    #[test]
+    fn hint_labels_are_typed_like_grid_labels() {
+        let (_, _, alphabets) = grid();
+        let labels = labels(&["AA", "AB", "BA"]);
+        let mut state = State::default();
+        let mut selection = state.selection();
+
+        // Letters count on release, and only when they lead to a label
+        for key in [
+            SelectionKey::Pressed('A'),
+            SelectionKey::Pressed('A'),
+            SelectionKey::Released('C'),
+            SelectionKey::Released('A'),
+        ] {
+            assert_eq!(selection.handle_hint(key, &labels, &alphabets), None);
+        }
+        assert_eq!(selection.focused_cell.typed, "A");
+
+        selection.handle_hint(SelectionKey::Backspace, &labels, &alphabets);
+        assert_eq!(selection.focused_cell.typed, "");
+
+        selection.handle_hint(SelectionKey::Released('A'), &labels, &alphabets);
+        assert_eq!(
+            selection.handle_hint(SelectionKey::Released('B'), &labels, &alphabets),
+            Some(HintAction::Pick(1))
+        );
+    }
+
+    #[test]
+    fn hint_exit_clears_then_leaves() {
+        let (_, cells, alphabets) = grid();
+        let mut state = State::default();
+        let mut selection = state.selection();
+
+        selection.handle_hint(SelectionKey::Pressed('B'), &cells, &alphabets);
+        assert_eq!(
+            selection.handle_hint(SelectionKey::Exit, &cells, &alphabets),
+            Some(HintAction::Leave)
+        );
+
+        let labels = labels(&["AA", "AB"]);
+        selection.handle_hint(SelectionKey::Released('A'), &labels, &alphabets);
+        assert_eq!(
+            selection.handle_hint(SelectionKey::Exit, &labels, &alphabets),
+            None
+        );
+        assert_eq!(selection.focused_cell.typed, "");
+        assert_eq!(
+            selection.handle_hint(SelectionKey::Exit, &labels, &alphabets),
+            Some(HintAction::Leave)
+        );
+    }
+}
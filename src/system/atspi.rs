@@ -0,0 +1,344 @@
+//! AT-SPI accessibility tree queries over D-Bus
+
+use crate::error::{Error, Result};
+use crate::models::hint::HintTarget;
+use serde::de::DeserializeOwned;
+use zbus::blocking::Connection;
+use zbus::zvariant::{OwnedObjectPath, Type};
+
+/// Registry holding the accessible applications
+const REGISTRY: &str = "org.a11y.atspi.Registry";
+const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+
+/// Interfaces used to walk the tree
+const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+const COMPONENT: &str = "org.a11y.atspi.Component";
+
+/// AT-SPI state bits
+const STATE_ACTIVE: u32 = 1;
+const STATE_SHOWING: u32 = 25;
+const STATE_VISIBLE: u32 = 30;
+
+/// AT-SPI roles of elements worth a hint
+const ACTIONABLE_ROLES: [u32; 16] = [
+    7,  // check box
+    8,  // check menu item
+    11, // combo box
+    32, // list item
+    35, // menu item
+    37, // page tab
+    40, // password text
+    43, // push button
+    44, // radio button
+    45, // radio menu item
+    51, // slider
+    52, // spin button
+    61, // text
+    62, // toggle button
+    79, // entry
+    88, // link
+];
+
+/// Extents in screen coordinates
+const COORD_TYPE_SCREEN: u32 = 0;
+
+/// Stop walking huge trees after this many elements
+const MAX_VISITED: usize = 5000;
+
+/// An object in the accessibility tree: bus name and object path
+type ObjectRef = (String, OwnedObjectPath);
+
+/// Get the visible actionable elements of the active window
+pub fn get_actionable_elements() -> Result<Vec<HintTarget>> {
+    let conn = connect()?;
+
+    // Find the active window among the windows of every application
+    let applications: Vec<ObjectRef> = call(&conn, REGISTRY, ROOT_PATH, ACCESSIBLE, "GetChildren")?;
+    let mut active_window = None;
+    'search: for (name, path) in &applications {
+        let Ok(windows) =
+            call::<Vec<ObjectRef>>(&conn, name, path.as_str(), ACCESSIBLE, "GetChildren")
+        else {
+            continue;
+        };
+        for window in windows {
+            if has_state(&get_states(&conn, &window), STATE_ACTIVE) {
+                active_window = Some(window);
+                break 'search;
+            }
+        }
+    }
+    let Some(window) = active_window else {
+        return Err(Error::Accessibility("no active window".into()));
+    };
+
+    // Walk the window, skipping subtrees that are not on screen
+    let mut targets = Vec::new();
+    let mut stack = vec![window];
+    let mut visited = 0;
+    while let Some(object) = stack.pop() {
+        visited += 1;
+        if visited > MAX_VISITED {
+            break;
+        }
+        let states = get_states(&conn, &object);
+        if !has_state(&states, STATE_SHOWING) || !has_state(&states, STATE_VISIBLE) {
+            continue;
+        }
+
+        let (name, path) = (&object.0, object.1.as_str());
+        let role: u32 = call(&conn, name, path, ACCESSIBLE, "GetRole").unwrap_or(0);
+        if ACTIONABLE_ROLES.contains(&role) {
+            if let Ok((x, y, width, height)) = call_with::<(i32, i32, i32, i32), _>(
+                &conn,
+                name,
+                path,
+                COMPONENT,
+                "GetExtents",
+                &COORD_TYPE_SCREEN,
+            ) {
+                if width > 0 && height > 0 {
+                    targets.push(HintTarget {
+                        x,
+                        y,
+                        width,
+                        height,
+                        window: None,
+                        title: String::new(),
+                    });
+                }
+            }
+        }
+
+        if let Ok(children) = call::<Vec<ObjectRef>>(&conn, name, path, ACCESSIBLE, "GetChildren") {
+            // Reverse so elements come out in tree order
+            stack.extend(children.into_iter().rev());
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Connect to the accessibility bus announced on the session bus
+fn connect() -> Result<Connection> {
+    let session = Connection::session()?;
+    let address: String = call(
+        &session,
+        "org.a11y.Bus",
+        "/org/a11y/bus",
+        "org.a11y.Bus",
+        "GetAddress",
+    )?;
+
+    Ok(zbus::blocking::connection::Builder::address(address.as_str())?.build()?)
+}
+
+/// Get the state bit set of an accessible object, empty if it is gone
+fn get_states(conn: &Connection, (name, path): &ObjectRef) -> Vec<u32> {
+    call(conn, name, path.as_str(), ACCESSIBLE, "GetState").unwrap_or_default()
+}
+
+/// Check a state bit
+fn has_state(states: &[u32], state: u32) -> bool {
+    states
+        .get((state / 32) as usize)
+        .is_some_and(|word| word & (1 << (state % 32)) != 0)
+}
+
+/// Call a method without arguments
+fn call<R>(conn: &Connection, name: &str, path: &str, interface: &str, method: &str) -> Result<R>
+where
+    R: DeserializeOwned + Type,
+{
+    call_with(conn, name, path, interface, method, &())
+}
+
+/// Call a method and deserialize the reply
+fn call_with<R, B>(
+    conn: &Connection,
+    name: &str,
+    path: &str,
+    interface: &str,
+    method: &str,
+    body: &B,
+) -> Result<R>
+where
+    R: DeserializeOwned + Type,
+    B: serde::Serialize + Type,
+{
+    let reply = conn.call_method(Some(name), path, Some(interface), method, body)?;
+    Ok(reply.body().deserialize()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::blocking::connection::Builder;
+
+    /// Accessible interface of one object in the fake tree
+    struct FakeAccessible {
+        role: u32,
+        states: Vec<u32>,
+        children: Vec<ObjectRef>,
+    }
+
+    #[zbus::interface(name = "org.a11y.atspi.Accessible")]
+    impl FakeAccessible {
+        fn get_role(&self) -> u32 {
+            self.role
+        }
+
+        fn get_state(&self) -> Vec<u32> {
+            self.states.clone()
+        }
+
+        fn get_children(&self) -> Vec<ObjectRef> {
+            self.children.clone()
+        }
+    }
+
+    /// Component interface of one object in the fake tree
+    struct FakeComponent {
+        extents: (i32, i32, i32, i32),
+    }
+
+    #[zbus::interface(name = "org.a11y.atspi.Component")]
+    impl FakeComponent {
+        fn get_extents(&self, _coord_type: u32) -> (i32, i32, i32, i32) {
+            self.extents
+        }
+    }
+
+    /// Accessibility bus launcher, pointing back at the session bus
+    struct FakeBus {
+        address: String,
+    }
+
+    #[zbus::interface(name = "org.a11y.Bus")]
+    impl FakeBus {
+        fn get_address(&self) -> String {
+            self.address.clone()
+        }
+    }
+
+    /// Get a state bit set
+    fn states(bits: &[u32]) -> Vec<u32> {
+        vec![bits.iter().fold(0, |set, bit| set | 1 << bit), 0]
+    }
+
+    /// Get a reference to an object of the fake registry
+    fn object(path: &str) -> ObjectRef {
+        (
+            REGISTRY.to_string(),
+            OwnedObjectPath::try_from(path).unwrap(),
+        )
+    }
+
+    #[test]
+    #[ignore = "needs a private D-Bus session, e.g. dbus-run-session"]
+    fn lists_visible_elements_of_the_active_window() {
+        let address = std::env::var("DBUS_SESSION_BUS_ADDRESS").unwrap();
+        let on_screen = [STATE_SHOWING, STATE_VISIBLE];
+
+        // (path, role, states, children, extents)
+        let tree = [
+            (ROOT_PATH, 0, vec![], vec!["/app"], (0, 0, 0, 0)),
+            (
+                "/app",
+                0,
+                vec![],
+                vec!["/app/inactive", "/app/window"],
+                (0, 0, 0, 0),
+            ),
+            (
+                "/app/inactive",
+                0,
+                on_screen.to_vec(),
+                vec!["/app/inactive/button"],
+                (0, 0, 0, 0),
+            ),
+            (
+                "/app/inactive/button",
+                43,
+                on_screen.to_vec(),
+                vec![],
+                (1, 1, 1, 1),
+            ),
+            (
+                "/app/window",
+                0,
+                vec![STATE_ACTIVE, STATE_SHOWING, STATE_VISIBLE],
+                vec![
+                    "/app/window/button",
+                    "/app/window/hidden",
+                    "/app/window/panel",
+                ],
+                (0, 0, 800, 600),
+            ),
+            (
+                "/app/window/button",
+                43,
+                on_screen.to_vec(),
+                vec![],
+                (10, 20, 30, 40),
+            ),
+            (
+                "/app/window/hidden",
+                43,
+                vec![STATE_VISIBLE],
+                vec![],
+                (2, 2, 2, 2),
+            ),
+            (
+                "/app/window/panel",
+                39,
+                on_screen.to_vec(),
+                vec!["/app/window/panel/link", "/app/window/panel/empty"],
+                (0, 0, 800, 600),
+            ),
+            (
+                "/app/window/panel/link",
+                88,
+                on_screen.to_vec(),
+                vec![],
+                (50, 60, 70, 80),
+            ),
+            (
+                "/app/window/panel/empty",
+                79,
+                on_screen.to_vec(),
+                vec![],
+                (3, 3, 0, 3),
+            ),
+        ];
+
+        let mut builder = Builder::session()
+            .unwrap()
+            .name(REGISTRY)
+            .unwrap()
+            .name("org.a11y.Bus")
+            .unwrap()
+            .serve_at("/org/a11y/bus", FakeBus { address })
+            .unwrap();
+        for (path, role, bits, children, extents) in tree {
+            let accessible = FakeAccessible {
+                role,
+                states: states(&bits),
+                children: children.into_iter().map(object).collect(),
+            };
+            builder = builder
+                .serve_at(path, accessible)
+                .unwrap()
+                .serve_at(path, FakeComponent { extents })
+                .unwrap();
+        }
+        let _service = builder.build().unwrap();
+
+        let targets = get_actionable_elements().unwrap();
+        let extents: Vec<_> = targets
+            .iter()
+            .map(|target| (target.x, target.y, target.width, target.height))
+            .collect();
+        assert_eq!(extents, [(10, 20, 30, 40), (50, 60, 70, 80)]);
+    }
+}
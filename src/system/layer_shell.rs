@@ -0,0 +1,693 @@
+//! Overlay on a `zwlr_layer_shell_v1` surface, for wlroots based compositors
+//!
+//! winit can't create layer-shell surfaces, so on Wayland the overlay runs
+//! its own event loop and paints the egui output in software.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use eframe::egui::epaint::ClippedShape;
+use eframe::egui::{
+    vec2, Context, Event, Modifiers, Pos2, RawInput, Rect, ViewportCommand, ViewportId,
+};
+use smithay_client_toolkit::reexports::calloop::{ping, EventLoop};
+use smithay_client_toolkit::reexports::calloop_wayland_source::WaylandSource;
+use smithay_client_toolkit::{
+    compositor::{CompositorHandler, CompositorState, Region},
+    delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_seat,
+    delegate_shm,
+    output::{OutputHandler, OutputState},
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    seat::{Capability, SeatHandler, SeatState},
+    shell::{
+        wlr_layer::{
+            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+            LayerSurfaceConfigure,
+        },
+        WaylandSurface,
+    },
+    shm::{
+        slot::{Buffer, SlotPool},
+        Shm, ShmHandler,
+    },
+};
+use wayland_client::globals::registry_queue_init;
+use wayland_client::protocol::wl_keyboard::{self, KeyState, KeymapFormat};
+use wayland_client::protocol::{wl_output, wl_seat, wl_shm, wl_surface};
+use wayland_client::{Connection, Dispatch, QueueHandle, WEnum};
+use xkbcommon::xkb;
+
+use crate::error::{Error, Result};
+use crate::input::keyboard::key_from_keysym;
+use crate::ui::raster::{self, Canvas, Rasterizer};
+
+/// Layer-shell namespace of the overlay, for compositor rules
+const NAMESPACE: &str = "kmouse";
+
+/// Initial size of the shared memory pool, grown to fit the output
+const POOL_SIZE: usize = 1920 * 1080 * 4;
+
+/// Buffers painted in turn, so one can be painted while the compositor
+/// reads another
+const MAX_BUFFERS: usize = 2;
+
+/// How long to wait for the compositor to release a buffer
+const BUFFER_RETRY: Duration = Duration::from_millis(4);
+
+/// Overlay drawn on a layer-shell surface covering the first output
+///
+/// The surface sits above every window and panel, takes the keyboard
+/// exclusively while it is shown and lets the pointer through.
+pub struct LayerShellOverlay {
+    event_loop: EventLoop<'static, OverlayState>,
+    state: OverlayState,
+}
+
+impl LayerShellOverlay {
+    /// Connect to the compositor, failing when it has no layer shell
+    pub fn new() -> Result<Self> {
+        let conn = Connection::connect_to_env()?;
+        let (globals, mut queue) = registry_queue_init::<OverlayState>(&conn)?;
+        let qh = queue.handle();
+
+        let shm = Shm::bind(&globals, &qh)?;
+        let pool = SlotPool::new(POOL_SIZE, &shm).map_err(|e| Error::Wayland(e.to_string()))?;
+        let mut state = OverlayState {
+            registry_state: RegistryState::new(&globals),
+            seat_state: SeatState::new(&globals, &qh),
+            output_state: OutputState::new(&globals, &qh),
+            compositor: CompositorState::bind(&globals, &qh)?,
+            layer_shell: LayerShell::bind(&globals, &qh)?,
+            shm,
+            pool,
+            qh,
+            layer: None,
+            buffers: Vec::new(),
+            shapes: Vec::new(),
+            painted_size: (0, 0, 0.0),
+            unpresented: None,
+            configured: false,
+            size: (0, 0),
+            scale: 1,
+            keyboard: None,
+            xkb_context: xkb::Context::new(xkb::CONTEXT_NO_FLAGS),
+            xkb_state: None,
+            focused: false,
+            modifiers: Modifiers::default(),
+            events: Vec::new(),
+            repaint_at: Arc::new(Mutex::new(None)),
+            closed: false,
+        };
+
+        // Learn the outputs and seats before the first surface is placed
+        queue.roundtrip(&mut state)?;
+
+        let event_loop = EventLoop::try_new().map_err(|e| Error::Wayland(e.to_string()))?;
+        WaylandSource::new(conn, queue)
+            .insert(event_loop.handle())
+            .map_err(|e| Error::Wayland(e.error.to_string()))?;
+
+        Ok(Self { event_loop, state })
+    }
+
+    /// Show the overlay and run `ui` for every frame until it closes
+    pub fn run(mut self, mut ui: impl FnMut(&Context)) -> Result<()> {
+        let ctx = Context::default();
+
+        // Input threads ask for frames through the context; wake the loop
+        let (ping, ping_source) = ping::make_ping()?;
+        self.event_loop
+            .handle()
+            .insert_source(ping_source, |_, _, _| {})
+            .map_err(|e| Error::Wayland(e.error.to_string()))?;
+        let repaint_at = Arc::clone(&self.state.repaint_at);
+        ctx.set_request_repaint_callback(move |info| {
+            schedule(&repaint_at, info.delay);
+            ping.ping();
+        });
+
+        let started = Instant::now();
+        let mut rasterizer = Rasterizer::new();
+        self.state.show();
+        while !self.state.closed {
+            // Wait for the compositor to size a new surface before drawing
+            let timeout = if self.state.awaiting_configure() {
+                None
+            } else {
+                self.state.repaint_in()
+            };
+            self.event_loop
+                .dispatch(timeout, &mut self.state)
+                .map_err(|e| Error::Wayland(e.to_string()))?;
+            if self.state.awaiting_configure() || !self.state.take_repaint() {
+                continue;
+            }
+
+            let output = ctx.run(self.state.take_input(started), &mut ui);
+            rasterizer.set_textures(&output.textures_delta);
+            if let Some(viewport) = output.viewport_output.get(&ViewportId::ROOT) {
+                for command in &viewport.commands {
+                    self.state.apply(command);
+                }
+                if viewport.repaint_delay < Duration::MAX {
+                    schedule(&self.state.repaint_at, viewport.repaint_delay);
+                }
+            }
+
+            if self.state.layer.is_some() && self.state.configured {
+                // New textures, such as font glyphs, can change any shape
+                let textures_changed = !output.textures_delta.set.is_empty();
+                self.state.draw(
+                    &ctx,
+                    &rasterizer,
+                    output.shapes,
+                    output.pixels_per_point,
+                    textures_changed,
+                )?;
+            }
+            rasterizer.free_textures(&output.textures_delta);
+        }
+
+        Ok(())
+    }
+}
+
+/// Ask for a frame after `delay`, unless one is due sooner
+fn schedule(repaint_at: &Mutex<Option<Instant>>, delay: Duration) {
+    let at = Instant::now() + delay;
+    let mut repaint_at = repaint_at.lock().unwrap();
+    if repaint_at.map_or(true, |current| at < current) {
+        *repaint_at = Some(at);
+    }
+}
+
+/// A buffer of the layer surface, with the pixels it is behind on
+struct SurfaceBuffer {
+    buffer: Buffer,
+    stale: Option<[usize; 4]>,
+}
+
+/// Wayland objects and input of the overlay
+struct OverlayState {
+    registry_state: RegistryState,
+    seat_state: SeatState,
+    output_state: OutputState,
+    compositor: CompositorState,
+    layer_shell: LayerShell,
+    shm: Shm,
+    pool: SlotPool,
+    qh: QueueHandle<Self>,
+
+    /// Layer surface while the overlay is shown
+    layer: Option<LayerSurface>,
+    /// Buffers of the surface, reused from frame to frame
+    buffers: Vec<SurfaceBuffer>,
+    /// Shapes of the last frame, to find what the next one changes
+    shapes: Vec<ClippedShape>,
+    /// Width and height in pixels and pixels per point of the buffers
+    painted_size: (usize, usize, f32),
+    /// Pixels changed since the surface was last committed
+    unpresented: Option<[usize; 4]>,
+    /// Whether the compositor sized the current layer surface
+    configured: bool,
+    /// Size of the last layer surface, in logical pixels
+    size: (u32, u32),
+    /// Scale of the output the surface is on
+    scale: i32,
+
+    keyboard: Option<wl_keyboard::WlKeyboard>,
+    xkb_context: xkb::Context,
+    /// Keymap and modifiers of the keyboard, once the compositor sent them
+    xkb_state: Option<xkb::State>,
+    /// Whether the layer surface has the keyboard focus
+    focused: bool,
+    modifiers: Modifiers,
+    /// Input for the next frame
+    events: Vec<Event>,
+
+    /// When the next frame is due, shared with the repaint callback
+    repaint_at: Arc<Mutex<Option<Instant>>>,
+    closed: bool,
+}
+
+impl OverlayState {
+    /// Create the layer surface over the first output, unless it is shown
+    fn show(&mut self) {
+        if self.layer.is_some() {
+            return;
+        }
+
+        let output = self.output_state.outputs().next();
+        if let Some(info) = output.as_ref().and_then(|o| self.output_state.info(o)) {
+            self.scale = info.scale_factor;
+        }
+
+        let surface = self.compositor.create_surface(&self.qh);
+        let layer = self.layer_shell.create_layer_surface(
+            &self.qh,
+            surface,
+            Layer::Overlay,
+            Some(NAMESPACE),
+            output.as_ref(),
+        );
+
+        // Cover the whole output, panels included, so the overlay is at a
+        // known position and clicks map to the output directly
+        layer.set_anchor(Anchor::all());
+        layer.set_exclusive_zone(-1);
+        layer.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+
+        // An empty input region passes the pointer through
+        match Region::new(&self.compositor) {
+            Ok(region) => layer.set_input_region(Some(region.wl_region())),
+            Err(e) => eprintln!("Failed to pass the pointer through the overlay: {}", e),
+        }
+
+        // The first commit has no buffer; the compositor answers with the size
+        layer.commit();
+        self.layer = Some(layer);
+        self.configured = false;
+    }
+
+    /// Destroy the layer surface, handing the keyboard back
+    fn hide(&mut self) {
+        self.layer = None;
+        self.painted_size = (0, 0, 0.0);
+        self.configured = false;
+        self.focused = false;
+    }
+
+    /// Apply a viewport command sent by the UI or the input threads
+    fn apply(&mut self, command: &ViewportCommand) {
+        match command {
+            ViewportCommand::Visible(true) => self.show(),
+            ViewportCommand::Visible(false) => self.hide(),
+            ViewportCommand::Close => self.closed = true,
+            _ => {}
+        }
+    }
+
+    /// Check if a new layer surface waits for its size
+    fn awaiting_configure(&self) -> bool {
+        self.layer.is_some() && !self.configured
+    }
+
+    /// Get the time until the next frame, or `None` without one
+    fn repaint_in(&self) -> Option<Duration> {
+        let repaint_at = *self.repaint_at.lock().unwrap();
+        repaint_at.map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Check if a frame is due, consuming it
+    fn take_repaint(&mut self) -> bool {
+        let mut repaint_at = self.repaint_at.lock().unwrap();
+        match *repaint_at {
+            Some(at) if at <= Instant::now() => {
+                *repaint_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Ask for a frame as soon as possible
+    fn request_repaint(&self) {
+        schedule(&self.repaint_at, Duration::ZERO);
+    }
+
+    /// Take the input gathered since the last frame
+    fn take_input(&mut self, started: Instant) -> RawInput {
+        let (width, height) = self.size;
+        let screen_rect = Rect::from_min_size(Pos2::ZERO, vec2(width as f32, height as f32));
+        let mut input = RawInput {
+            screen_rect: Some(screen_rect),
+            time: Some(started.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            focused: self.focused,
+            ..RawInput::default()
+        };
+
+        // The surface covers the output from its corner
+        let viewport = input.viewports.entry(ViewportId::ROOT).or_default();
+        viewport.native_pixels_per_point = Some(self.scale as f32);
+        viewport.inner_rect = Some(screen_rect);
+        viewport.outer_rect = Some(screen_rect);
+        viewport.focused = Some(self.focused);
+        input
+    }
+
+    /// Paint the pixels a frame changed into a free buffer and present it
+    ///
+    /// Each buffer is only repainted where it differs from the frame, and
+    /// nothing is committed when the frame looks like the last one.
+    fn draw(
+        &mut self,
+        ctx: &Context,
+        rasterizer: &Rasterizer,
+        shapes: Vec<ClippedShape>,
+        pixels_per_point: f32,
+        textures_changed: bool,
+    ) -> Result<()> {
+        let Some(layer) = self.layer.as_ref() else {
+            return Ok(());
+        };
+        let width = self.size.0 as usize * self.scale as usize;
+        let height = self.size.1 as usize * self.scale as usize;
+        let whole = [0, 0, width, height];
+
+        // A new surface, size or scale starts over with new buffers
+        let size = (width, height, pixels_per_point);
+        let damage = if self.painted_size != size {
+            self.painted_size = size;
+            self.buffers.clear();
+            Some(whole)
+        } else if textures_changed {
+            Some(whole)
+        } else {
+            raster::damage(&self.shapes, &shapes, pixels_per_point, width, height)
+        };
+        for buffer in &mut self.buffers {
+            buffer.stale = raster::union(buffer.stale, damage);
+        }
+        self.unpresented = raster::union(self.unpresented, damage);
+        self.shapes = shapes;
+        let Some(unpresented) = self.unpresented else {
+            return Ok(());
+        };
+
+        // Paint into a buffer the compositor is done with, if there is one
+        let free = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.buffer.canvas(&mut self.pool).is_some());
+        let index = match free {
+            Some(index) => index,
+            None if self.buffers.len() < MAX_BUFFERS => {
+                let (buffer, _) = self
+                    .pool
+                    .create_buffer(
+                        width as i32,
+                        height as i32,
+                        width as i32 * 4,
+                        wl_shm::Format::Argb8888,
+                    )
+                    .map_err(|e| Error::Wayland(e.to_string()))?;
+                self.buffers.push(SurfaceBuffer {
+                    buffer,
+                    stale: Some(whole),
+                });
+                self.buffers.len() - 1
+            }
+            None => {
+                schedule(&self.repaint_at, BUFFER_RETRY);
+                return Ok(());
+            }
+        };
+
+        let buffer = &mut self.buffers[index];
+        if let Some(stale) = buffer.stale.take() {
+            let pixels = buffer
+                .buffer
+                .canvas(&mut self.pool)
+                .ok_or_else(|| Error::Wayland("buffer is still in use".into()))?;
+            let primitives = ctx.tessellate(self.shapes.clone(), pixels_per_point);
+            let mut canvas = Canvas {
+                pixels,
+                width,
+                height,
+            };
+            rasterizer.paint(&primitives, pixels_per_point, &mut canvas, stale);
+        }
+
+        let [x0, y0, x1, y1] = unpresented;
+        let surface = layer.wl_surface();
+        surface.set_buffer_scale(self.scale);
+        surface.damage_buffer(x0 as i32, y0 as i32, (x1 - x0) as i32, (y1 - y0) as i32);
+        buffer
+            .buffer
+            .attach_to(surface)
+            .map_err(|e| Error::Wayland(e.to_string()))?;
+        layer.commit();
+        self.unpresented = None;
+        Ok(())
+    }
+
+    /// Queue a key event for the next frame
+    fn push_key(&mut self, keysym: xkb::Keysym, pressed: bool) {
+        let Some(key) = key_from_keysym(keysym.raw()) else {
+            return;
+        };
+        self.events.push(Event::Key {
+            key,
+            physical_key: None,
+            pressed,
+            repeat: false,
+            modifiers: self.modifiers,
+        });
+        self.request_repaint();
+    }
+}
+
+impl CompositorHandler for OverlayState {
+    fn scale_factor_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        new_factor: i32,
+    ) {
+        self.scale = new_factor.max(1);
+        self.request_repaint();
+    }
+
+    fn transform_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_transform: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _time: u32,
+    ) {
+    }
+
+    fn surface_enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl OutputHandler for OverlayState {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn update_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn output_destroyed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl LayerShellHandler for OverlayState {
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
+        // The compositor took the surface away, e.g. with its output; the
+        // next show places a new one
+        self.hide();
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        let (width, height) = configure.new_size;
+        if width > 0 && height > 0 {
+            self.size = (width, height);
+        }
+        self.configured = true;
+        self.request_repaint();
+    }
+}
+
+impl SeatHandler for OverlayState {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: wl_seat::WlSeat) {}
+
+    fn new_capability(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Keyboard && self.keyboard.is_none() {
+            self.keyboard = Some(seat.get_keyboard(qh, ()));
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Keyboard {
+            if let Some(keyboard) = self.keyboard.take() {
+                keyboard.release();
+            }
+            self.xkb_state = None;
+        }
+    }
+
+    fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: wl_seat::WlSeat) {
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for OverlayState {
+    fn event(
+        state: &mut Self,
+        _keyboard: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_keyboard::Event::Keymap {
+                format: WEnum::Value(KeymapFormat::XkbV1),
+                fd,
+                size,
+            } => {
+                // SAFETY: the compositor shares a keymap of `size` bytes
+                // through `fd`, which is only read
+                let keymap = unsafe {
+                    xkb::Keymap::new_from_fd(
+                        &state.xkb_context,
+                        fd,
+                        size as usize,
+                        xkb::KEYMAP_FORMAT_TEXT_V1,
+                        xkb::KEYMAP_COMPILE_NO_FLAGS,
+                    )
+                };
+                match keymap {
+                    Ok(Some(keymap)) => state.xkb_state = Some(xkb::State::new(&keymap)),
+                    Ok(None) => eprintln!("Failed to compile the keymap"),
+                    Err(e) => eprintln!("Failed to read the keymap: {}", e),
+                }
+            }
+            wl_keyboard::Event::Enter { surface, .. } => {
+                state.focused = state
+                    .layer
+                    .as_ref()
+                    .is_some_and(|layer| layer.wl_surface() == &surface);
+                state.request_repaint();
+            }
+            wl_keyboard::Event::Leave { .. } => {
+                state.focused = false;
+                state.request_repaint();
+            }
+            wl_keyboard::Event::Key {
+                key,
+                state: WEnum::Value(key_state),
+                ..
+            } => {
+                // Wayland sends evdev codes, which are 8 below X keycodes
+                let Some(xkb_state) = state.xkb_state.as_ref() else {
+                    return;
+                };
+                let keysym = xkb_state.key_get_one_sym(xkb::Keycode::new(key + 8));
+                state.push_key(keysym, key_state == KeyState::Pressed);
+            }
+            wl_keyboard::Event::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+                ..
+            } => {
+                let Some(xkb_state) = state.xkb_state.as_mut() else {
+                    return;
+                };
+                xkb_state.update_mask(mods_depressed, mods_latched, mods_locked, 0, 0, group);
+                let active = |name| xkb_state.mod_name_is_active(name, xkb::STATE_MODS_EFFECTIVE);
+                state.modifiers = Modifiers {
+                    alt: active(xkb::MOD_NAME_ALT),
+                    ctrl: active(xkb::MOD_NAME_CTRL),
+                    shift: active(xkb::MOD_NAME_SHIFT),
+                    command: active(xkb::MOD_NAME_CTRL),
+                    ..Modifiers::default()
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ShmHandler for OverlayState {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl ProvidesRegistryState for OverlayState {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+
+    registry_handlers![OutputState, SeatState];
+}
+
+delegate_compositor!(OverlayState);
+delegate_output!(OverlayState);
+delegate_shm!(OverlayState);
+delegate_seat!(OverlayState);
+delegate_layer!(OverlayState);
+delegate_registry!(OverlayState);
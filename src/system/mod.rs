@@ -1,3 +1,8 @@
 //! System interaction module
 
+pub mod atspi;
+#[cfg(feature = "wayland-overlay")]
+pub mod layer_shell;
+pub mod reload;
+pub mod wayland;
 pub mod x11;
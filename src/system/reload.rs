@@ -0,0 +1,76 @@
+//! Configuration reload triggers: config file changes and SIGHUP
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+
+use crate::config::{self, SharedConfig};
+use crate::error::{Error, Result};
+
+/// Editors save in several steps; wait this long for the last one
+const SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// Reload the configuration whenever the config file changes
+pub fn start_config_watcher(config: SharedConfig) -> Result<()> {
+    let Some(path) = config::config_path() else {
+        return Ok(());
+    };
+    let Some(dir) = path.parent() else {
+        return Ok(());
+    };
+
+    // Create the directory, so a config file written later is picked up
+    std::fs::create_dir_all(dir)?;
+
+    // Watch the directory, since editors replace the file instead of writing it
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|e| Error::Config(format!("cannot watch the config file: {}", e)))?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(|e| Error::Config(format!("cannot watch {}: {}", dir.display(), e)))?;
+
+    std::thread::spawn(move || {
+        // The watcher stops when dropped
+        let _watcher = watcher;
+
+        for event in &receiver {
+            let Ok(event) = event else {
+                continue;
+            };
+            if matches!(event.kind, EventKind::Access(_)) || !event.paths.contains(&path) {
+                continue;
+            }
+
+            std::thread::sleep(SETTLE_DELAY);
+            while receiver.try_recv().is_ok() {}
+
+            reload(&config);
+        }
+    });
+
+    Ok(())
+}
+
+/// Reload the configuration on SIGHUP
+pub fn start_signal_handler(config: SharedConfig) -> Result<()> {
+    let mut signals = Signals::new([SIGHUP])?;
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            reload(&config);
+        }
+    });
+
+    Ok(())
+}
+
+/// Reload the configuration, reporting invalid files
+fn reload(config: &SharedConfig) {
+    if let Err(e) = config.reload() {
+        eprintln!("Failed to reload configuration: {}", e);
+    }
+}
@@ -0,0 +1,230 @@
+//! Wayland session support
+
+use std::time::Instant;
+
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::wl_output::{self, Mode};
+use wayland_client::protocol::wl_pointer::{Axis, AxisSource, ButtonState};
+use wayland_client::protocol::{wl_registry, wl_seat};
+use wayland_client::{delegate_noop, Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum};
+use wayland_protocols_wlr::virtual_pointer::v1::client::{
+    zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1,
+    zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1,
+};
+
+use crate::error::{Error, Result};
+use crate::input::pointer::{PointerBackend, PointerButton, ScrollAxis};
+
+/// Linux input event codes of the mouse buttons
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+
+/// Scroll distance of one wheel notch, in surface-local units
+const AXIS_STEP: f64 = 15.0;
+
+/// Check if Kmouse runs in a Wayland session
+pub fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some_and(|display| !display.is_empty())
+}
+
+/// Event handler collecting the current mode of an output
+#[derive(Default)]
+struct OutputState {
+    size: Option<(i32, i32)>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for OutputState {
+    fn event(
+        _state: &mut Self,
+        _registry: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for OutputState {
+    fn event(
+        state: &mut Self,
+        _output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Mode {
+            flags: WEnum::Value(flags),
+            width,
+            height,
+            ..
+        } = event
+        {
+            if flags.contains(Mode::Current) {
+                state.size = Some((width, height));
+            }
+        }
+    }
+}
+
+/// Get the size of the first output in pixels, for sessions without XWayland
+pub fn get_output_size() -> Result<(i32, i32)> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init::<OutputState>(&conn)?;
+    let output: wl_output::WlOutput = globals.bind(&queue.handle(), 1..=4, ())?;
+
+    let mut state = OutputState::default();
+    queue.roundtrip(&mut state)?;
+    if output.version() >= 3 {
+        output.release();
+    }
+
+    state
+        .size
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| Error::Wayland("output has no current mode".to_string()))
+}
+
+/// Event handler for the virtual pointer connection; no events matter
+struct VirtualPointerState;
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for VirtualPointerState {
+    fn event(
+        _state: &mut Self,
+        _registry: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(VirtualPointerState: ignore wl_seat::WlSeat);
+delegate_noop!(VirtualPointerState: ignore wl_output::WlOutput);
+delegate_noop!(VirtualPointerState: ZwlrVirtualPointerManagerV1);
+delegate_noop!(VirtualPointerState: ZwlrVirtualPointerV1);
+
+/// Pointer backend using the `zwlr_virtual_pointer_v1` protocol of wlroots
+/// based compositors
+pub struct VirtualPointerBackend {
+    conn: Connection,
+    /// Queue of the pointer objects, which must outlive them
+    _queue: EventQueue<VirtualPointerState>,
+    pointer: ZwlrVirtualPointerV1,
+    /// Size of the output, which absolute motion is relative to
+    extent: (u32, u32),
+    started: Instant,
+}
+
+impl VirtualPointerBackend {
+    /// Connect to the compositor and create a virtual pointer on the default
+    /// seat, for an output of `width` x `height` pixels
+    ///
+    /// Motion is mapped to the first output, which the layer-shell overlay
+    /// covers. That takes version 2 of the protocol; version 1 maps motion
+    /// to the whole output layout, whose extent is unknown here.
+    pub fn new(width: i32, height: i32) -> Result<Self> {
+        let extent = match (u32::try_from(width), u32::try_from(height)) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+            _ => {
+                return Err(Error::Wayland(format!(
+                    "invalid output size {}x{}",
+                    width, height
+                )))
+            }
+        };
+
+        let conn = Connection::connect_to_env()?;
+        let (globals, queue) = registry_queue_init::<VirtualPointerState>(&conn)?;
+        let qh = queue.handle();
+
+        let manager: ZwlrVirtualPointerManagerV1 = globals.bind(&qh, 2..=2, ()).map_err(|e| {
+            Error::Wayland(format!(
+                "the compositor needs version 2 of the wlroots virtual pointer protocol: {}",
+                e
+            ))
+        })?;
+        let seat: Option<wl_seat::WlSeat> = globals.bind(&qh, 1..=7, ()).ok();
+        let output: wl_output::WlOutput = globals.bind(&qh, 1..=4, ())?;
+        let pointer =
+            manager.create_virtual_pointer_with_output(seat.as_ref(), Some(&output), &qh, ());
+        conn.flush()?;
+
+        Ok(Self {
+            conn,
+            _queue: queue,
+            pointer,
+            extent,
+            started: Instant::now(),
+        })
+    }
+
+    /// Get the timestamp of an event, in milliseconds
+    fn time(&self) -> u32 {
+        self.started.elapsed().as_millis() as u32
+    }
+
+    /// End the current group of events and send it
+    fn commit(&self) -> Result<()> {
+        self.pointer.frame();
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Send a button event
+    fn button(&mut self, button: PointerButton, state: ButtonState) -> Result<()> {
+        let code = match button {
+            PointerButton::Left => BTN_LEFT,
+            PointerButton::Right => BTN_RIGHT,
+            PointerButton::Middle => BTN_MIDDLE,
+        };
+        self.pointer.button(self.time(), code, state);
+        self.commit()
+    }
+}
+
+impl PointerBackend for VirtualPointerBackend {
+    fn move_to(&mut self, x: i32, y: i32) -> Result<()> {
+        // Keep the pointer on the last pixel of the output at most
+        let (width, height) = self.extent;
+        let clamp = |value: i32, extent: u32| u32::try_from(value).unwrap_or(0).min(extent - 1);
+        self.pointer.motion_absolute(
+            self.time(),
+            clamp(x, width),
+            clamp(y, height),
+            width,
+            height,
+        );
+        self.commit()
+    }
+
+    fn press(&mut self, button: PointerButton) -> Result<()> {
+        self.button(button, ButtonState::Pressed)
+    }
+
+    fn release(&mut self, button: PointerButton) -> Result<()> {
+        self.button(button, ButtonState::Released)
+    }
+
+    fn scroll(&mut self, length: i32, axis: ScrollAxis) -> Result<()> {
+        let axis = match axis {
+            ScrollAxis::Vertical => Axis::VerticalScroll,
+            ScrollAxis::Horizontal => Axis::HorizontalScroll,
+        };
+        let time = self.time();
+        self.pointer.axis_source(AxisSource::Wheel);
+        self.pointer
+            .axis_discrete(time, axis, length as f64 * AXIS_STEP, length);
+        self.commit()
+    }
+}
+
+impl Drop for VirtualPointerBackend {
+    fn drop(&mut self) {
+        self.pointer.destroy();
+        let _ = self.conn.flush();
+    }
+}
@@ -1,14 +1,28 @@
 //! X11 window system interactions
 
 use crate::error::Result;
-use x11rb::connection::Connection;
-use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+use crate::input::pointer::{PointerBackend, PointerButton, ScrollAxis};
+use crate::models::hint::HintTarget;
+use crate::models::monitor::Monitor;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::randr::ConnectionExt as RandrConnectionExt;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt, EventMask, GrabMode, GrabStatus, KeyButMask,
+    KeyPressEvent, Keycode, Keysym, MapState, Window, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT,
+    MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::{self, ConnectionExt as XTestConnectionExt};
+use x11rb::protocol::Event;
 use x11rb::rust_connection::RustConnection;
+use x11rb::CURRENT_TIME;
+
+/// Longest window title shown next to a window hint
+const MAX_TITLE_LEN: usize = 40;
 
 /// Get the work area from the X11 window system
 pub fn get_work_area() -> Result<(i32, i32, i32, i32)> {
     // Connect to the X11 server
-    let (conn, screen_num) = RustConnection::connect(None).expect("Error: rust connection");
+    let (conn, screen_num) = RustConnection::connect(None)?;
     let screen = &conn.setup().roots[screen_num];
 
     // Intern the _NET_WORKAREA atom
@@ -36,3 +50,474 @@ pub fn get_work_area() -> Result<(i32, i32, i32, i32)> {
         "Failed to retrieve _NET_WORKAREA".into(),
     ))
 }
+
+/// Get the size of the root window (the whole X screen) in pixels
+pub fn get_screen_size() -> Result<(i32, i32)> {
+    // Connect to the X11 server
+    let (conn, screen_num) = RustConnection::connect(None)?;
+    let screen = &conn.setup().roots[screen_num];
+
+    // Query the current root geometry, which follows RandR resizes
+    let geometry = conn.get_geometry(screen.root)?.reply()?;
+
+    Ok((geometry.width as i32, geometry.height as i32))
+}
+
+/// Get the active monitors from RandR, ordered left to right
+pub fn get_monitors() -> Result<Vec<Monitor>> {
+    // Connect to the X11 server
+    let (conn, screen_num) = RustConnection::connect(None)?;
+    let screen = &conn.setup().roots[screen_num];
+
+    // Announce the RandR version we speak; screen resources need 1.3
+    conn.randr_query_version(1, 3)?.reply()?;
+    let resources = conn
+        .randr_get_screen_resources_current(screen.root)?
+        .reply()?;
+
+    let mut monitors = Vec::new();
+    for &crtc in &resources.crtcs {
+        let info = conn
+            .randr_get_crtc_info(crtc, resources.config_timestamp)?
+            .reply()?;
+
+        // Skip disabled CRTCs
+        if info.mode == 0 || info.outputs.is_empty() {
+            continue;
+        }
+
+        let monitor = Monitor {
+            x: info.x as i32,
+            y: info.y as i32,
+            width: info.width as i32,
+            height: info.height as i32,
+        };
+
+        // Mirrored outputs share a CRTC rectangle
+        if !monitors.contains(&monitor) {
+            monitors.push(monitor);
+        }
+    }
+
+    monitors.sort_by_key(|m| (m.x, m.y));
+    Ok(monitors)
+}
+
+/// Get the mapped top-level windows from `_NET_CLIENT_LIST`
+pub fn get_client_windows() -> Result<Vec<HintTarget>> {
+    // Connect to the X11 server
+    let (conn, screen_num) = RustConnection::connect(None)?;
+    let screen = &conn.setup().roots[screen_num];
+
+    let client_list = intern_atom(&conn, b"_NET_CLIENT_LIST")?;
+    let prop = conn
+        .get_property(
+            false,
+            screen.root,
+            client_list,
+            AtomEnum::WINDOW,
+            0,
+            u32::MAX,
+        )?
+        .reply()?;
+    let Some(windows) = prop.value32() else {
+        return Err(crate::error::Error::X11(
+            "Failed to retrieve _NET_CLIENT_LIST".into(),
+        ));
+    };
+
+    let mut targets = Vec::new();
+    for window in windows {
+        // Minimized windows and windows on other desktops are unmapped
+        let Ok(attributes) = conn.get_window_attributes(window)?.reply() else {
+            continue;
+        };
+        if attributes.map_state != MapState::VIEWABLE {
+            continue;
+        }
+
+        // Window positions are relative to their parent, usually a frame
+        let (Ok(geometry), Ok(origin)) = (
+            conn.get_geometry(window)?.reply(),
+            conn.translate_coordinates(window, screen.root, 0, 0)?
+                .reply(),
+        ) else {
+            continue;
+        };
+
+        targets.push(HintTarget {
+            x: origin.dst_x as i32,
+            y: origin.dst_y as i32,
+            width: geometry.width as i32,
+            height: geometry.height as i32,
+            window: Some(window),
+            title: get_window_title(&conn, window).unwrap_or_default(),
+        });
+    }
+
+    Ok(targets)
+}
+
+/// Ask the window manager to raise and focus `window`
+pub fn activate_window(window: Window) -> Result<()> {
+    // Connect to the X11 server
+    let (conn, screen_num) = RustConnection::connect(None)?;
+    let screen = &conn.setup().roots[screen_num];
+
+    // Source indication 2 tells the window manager a pager asked for it
+    let active_window = intern_atom(&conn, b"_NET_ACTIVE_WINDOW")?;
+    let event = ClientMessageEvent::new(32, window, active_window, [2, 0, 0, 0, 0]);
+    conn.send_event(
+        false,
+        screen.root,
+        EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+        event,
+    )?
+    .check()?;
+
+    Ok(())
+}
+
+/// Get the title of a window, preferring the UTF-8 `_NET_WM_NAME`
+fn get_window_title(conn: &RustConnection, window: Window) -> Result<String> {
+    let net_wm_name = intern_atom(conn, b"_NET_WM_NAME")?;
+    let utf8_string = intern_atom(conn, b"UTF8_STRING")?;
+
+    let mut title = conn
+        .get_property(false, window, net_wm_name, utf8_string, 0, u32::MAX)?
+        .reply()?
+        .value;
+    if title.is_empty() {
+        title = conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_NAME,
+                AtomEnum::STRING,
+                0,
+                u32::MAX,
+            )?
+            .reply()?
+            .value;
+    }
+
+    Ok(String::from_utf8_lossy(&title)
+        .chars()
+        .take(MAX_TITLE_LEN)
+        .collect())
+}
+
+/// Get an atom by name
+fn intern_atom(conn: &RustConnection, name: &[u8]) -> Result<Atom> {
+    Ok(conn.intern_atom(false, name)?.reply()?.atom)
+}
+
+/// A key event read while the keyboard is grabbed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrabbedKey {
+    /// Keysym of the key without modifiers
+    pub keysym: Keysym,
+    pub pressed: bool,
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+/// Exclusive keyboard grab on the root window
+///
+/// While grabbed, key events are only delivered to this connection. The
+/// grab is released on drop, and by the X server if the process dies.
+#[derive(Debug)]
+pub struct KeyboardGrab {
+    conn: RustConnection,
+    root: Window,
+    min_keycode: Keycode,
+    keysyms_per_keycode: u8,
+    keysyms: Vec<Keysym>,
+    grabbed: bool,
+}
+
+impl KeyboardGrab {
+    /// Connect to the X server and read the keyboard mapping
+    pub fn new() -> Result<Self> {
+        let (conn, screen_num) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+        let min_keycode = conn.setup().min_keycode;
+        let max_keycode = conn.setup().max_keycode;
+
+        let mapping = conn
+            .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+            .reply()?;
+
+        Ok(Self {
+            conn,
+            root,
+            min_keycode,
+            keysyms_per_keycode: mapping.keysyms_per_keycode,
+            keysyms: mapping.keysyms,
+            grabbed: false,
+        })
+    }
+
+    /// Check if the keyboard is grabbed
+    pub fn is_grabbed(&self) -> bool {
+        self.grabbed
+    }
+
+    /// Try to grab the keyboard; fails while another client holds a grab
+    pub fn grab(&mut self) -> Result<bool> {
+        if !self.grabbed {
+            let reply = self
+                .conn
+                .grab_keyboard(
+                    false,
+                    self.root,
+                    CURRENT_TIME,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                )?
+                .reply()?;
+            self.grabbed = reply.status == GrabStatus::SUCCESS;
+        }
+
+        Ok(self.grabbed)
+    }
+
+    /// Release the keyboard
+    pub fn ungrab(&mut self) {
+        if !self.grabbed {
+            return;
+        }
+        self.grabbed = false;
+
+        let released = self
+            .conn
+            .ungrab_keyboard(CURRENT_TIME)
+            .and_then(|_| self.conn.flush());
+        if let Err(e) = released {
+            eprintln!("Failed to release the keyboard: {}", e);
+        }
+    }
+
+    /// Read the key events received since the last call
+    pub fn poll_keys(&mut self) -> Result<Vec<GrabbedKey>> {
+        let mut keys = Vec::new();
+        while let Some(event) = self.conn.poll_for_event()? {
+            match event {
+                Event::KeyPress(event) => keys.push(self.grabbed_key(&event, true)),
+                Event::KeyRelease(event) => keys.push(self.grabbed_key(&event, false)),
+                _ => {}
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Translate a key event with the keyboard mapping
+    fn grabbed_key(&self, event: &KeyPressEvent, pressed: bool) -> GrabbedKey {
+        let index = event.detail.saturating_sub(self.min_keycode) as usize
+            * self.keysyms_per_keycode as usize;
+
+        GrabbedKey {
+            keysym: self.keysyms.get(index).copied().unwrap_or(0),
+            pressed,
+            shift: event.state.contains(KeyButMask::SHIFT),
+            ctrl: event.state.contains(KeyButMask::CONTROL),
+            alt: event.state.contains(KeyButMask::MOD1),
+        }
+    }
+}
+
+impl Drop for KeyboardGrab {
+    fn drop(&mut self) {
+        self.ungrab();
+    }
+}
+
+/// Pointer backend faking input through the XTEST extension
+#[derive(Debug)]
+pub struct XTestBackend {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl XTestBackend {
+    /// Connect to the X server and check that it supports XTEST
+    pub fn new() -> Result<Self> {
+        let (conn, screen_num) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+
+        if conn
+            .extension_information(xtest::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Err(crate::error::Error::X11(
+                "XTEST extension is not available".into(),
+            ));
+        }
+
+        Ok(Self { conn, root })
+    }
+
+    /// Fake a button press or release
+    fn button(&self, button: u8, pressed: bool) -> Result<()> {
+        let event_type = if pressed {
+            BUTTON_PRESS_EVENT
+        } else {
+            BUTTON_RELEASE_EVENT
+        };
+        self.conn
+            .xtest_fake_input(event_type, button, CURRENT_TIME, x11rb::NONE, 0, 0, 0)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// Get the X button number of a pointer button
+fn button_number(button: PointerButton) -> u8 {
+    match button {
+        PointerButton::Left => 1,
+        PointerButton::Middle => 2,
+        PointerButton::Right => 3,
+    }
+}
+
+impl PointerBackend for XTestBackend {
+    fn move_to(&mut self, x: i32, y: i32) -> Result<()> {
+        // X coordinates are 16 bits wide
+        let (Ok(root_x), Ok(root_y)) = (i16::try_from(x), i16::try_from(y)) else {
+            return Err(crate::error::Error::Input(format!(
+                "pointer position ({}, {}) is out of X11 range",
+                x, y
+            )));
+        };
+
+        // Detail 0 makes the motion absolute, relative to `root`
+        self.conn.xtest_fake_input(
+            MOTION_NOTIFY_EVENT,
+            0,
+            CURRENT_TIME,
+            self.root,
+            root_x,
+            root_y,
+            0,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn press(&mut self, button: PointerButton) -> Result<()> {
+        self.button(button_number(button), true)
+    }
+
+    fn release(&mut self, button: PointerButton) -> Result<()> {
+        self.button(button_number(button), false)
+    }
+
+    fn scroll(&mut self, length: i32, axis: ScrollAxis) -> Result<()> {
+        // Wheel notches are clicks of buttons 4 to 7
+        let button = match (axis, length < 0) {
+            (ScrollAxis::Vertical, true) => 4,
+            (ScrollAxis::Vertical, false) => 5,
+            (ScrollAxis::Horizontal, true) => 6,
+            (ScrollAxis::Horizontal, false) => 7,
+        };
+        for _ in 0..length.unsigned_abs() {
+            self.button(button, true)?;
+            self.button(button, false)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use x11rb::protocol::xproto::{
+        ChangeWindowAttributesAux, CreateWindowAux, PropMode, WindowClass,
+    };
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::COPY_FROM_PARENT;
+
+    /// Plays the window manager on an X server that has none, e.g. under
+    /// `xvfb-run cargo test -- --ignored`
+    #[test]
+    #[ignore = "needs an X server without a window manager, e.g. Xvfb"]
+    fn lists_and_activates_client_windows() {
+        let (conn, screen_num) = RustConnection::connect(None).unwrap();
+        let root = conn.setup().roots[screen_num].root;
+
+        // Redirecting the root's substructure makes this connection the
+        // window manager, which receives activation requests
+        conn.change_window_attributes(
+            root,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::SUBSTRUCTURE_REDIRECT),
+        )
+        .unwrap()
+        .check()
+        .unwrap();
+
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            COPY_FROM_PARENT as u8,
+            window,
+            root,
+            10,
+            20,
+            300,
+            200,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            COPY_FROM_PARENT,
+            &CreateWindowAux::new(),
+        )
+        .unwrap();
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            b"kmouse test",
+        )
+        .unwrap();
+        conn.map_window(window).unwrap();
+
+        let client_list = intern_atom(&conn, b"_NET_CLIENT_LIST").unwrap();
+        conn.change_property32(
+            PropMode::REPLACE,
+            root,
+            client_list,
+            AtomEnum::WINDOW,
+            &[window],
+        )
+        .unwrap();
+        conn.sync().unwrap();
+
+        let targets = get_client_windows().unwrap();
+        let target = targets
+            .iter()
+            .find(|target| target.window == Some(window))
+            .expect("the mapped window is listed");
+        assert_eq!(
+            (target.x, target.y, target.width, target.height),
+            (10, 20, 300, 200)
+        );
+        assert_eq!(target.title, "kmouse test");
+
+        activate_window(window).unwrap();
+        let active_window = intern_atom(&conn, b"_NET_ACTIVE_WINDOW").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        loop {
+            match conn.poll_for_event().unwrap() {
+                Some(Event::ClientMessage(event)) if event.type_ == active_window => {
+                    assert_eq!(event.window, window);
+                    break;
+                }
+                Some(_) => {}
+                None if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                None => panic!("no _NET_ACTIVE_WINDOW request arrived"),
+            }
+        }
+    }
+}
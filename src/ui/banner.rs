@@ -0,0 +1,54 @@
+//! On-screen error banner
+
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use eframe::egui::{vec2, Align2, Color32, Context, FontId, Id, LayerId, Order, Rect};
+
+use crate::input::keyboard::CTX_CELL;
+
+/// How long an error stays on screen
+const BANNER_DURATION: Duration = Duration::from_secs(5);
+
+/// Latest error and when it was reported
+static LAST_ERROR: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+/// Report an error on stderr and in the overlay
+pub fn report_error(context: &str, error: impl Display) {
+    let message = format!("{}: {}", context, error);
+    eprintln!("{}", message);
+
+    *LAST_ERROR.lock().unwrap() = Some((message, Instant::now()));
+    if let Some(ctx) = CTX_CELL.get() {
+        ctx.request_repaint();
+    }
+}
+
+/// Draw the latest error along the bottom of `area`, above everything else,
+/// until it expires
+pub fn draw_error_banner(ctx: &Context, area: Rect) {
+    let mut last_error = LAST_ERROR.lock().unwrap();
+    let Some(shown_for) = last_error.as_ref().map(|(_, at)| at.elapsed()) else {
+        return;
+    };
+    if shown_for >= BANNER_DURATION {
+        *last_error = None;
+        return;
+    }
+    ctx.request_repaint_after(BANNER_DURATION - shown_for);
+    let Some((message, _)) = last_error.as_ref() else {
+        return;
+    };
+
+    let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("error_banner")));
+    let rect = Rect::from_min_max(area.left_bottom() - vec2(0.0, 40.0), area.right_bottom());
+    painter.rect_filled(rect, 0.0, Color32::from_rgba_unmultiplied(160, 20, 20, 220));
+    painter.text(
+        rect.center(),
+        Align2::CENTER_CENTER,
+        message,
+        FontId::proportional(18.0),
+        Color32::WHITE,
+    );
+}
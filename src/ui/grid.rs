@@ -1,226 +1,492 @@
 //! Grid drawing and interaction logic
 
-use eframe::egui::{vec2, Align2, Color32, Context, FontId, Key, Rect, Stroke, Ui};
-use enigo::Enigo;
+use eframe::egui::{
+    vec2, Align2, Color32, Context, Event, FontId, Key, Modifiers, Rect, Stroke, Ui,
+};
 
+use crate::config::AppConfig;
+use crate::input::mouse::{MouseAction, ScrollDirection, Scroller};
+use crate::input::pointer::PointerBackend;
 use crate::input::{keyboard, mouse};
-use crate::models::cell::{CellPlural, CellSingular, FocusedCell};
-use crate::models::margin::Margin;
-
-/// Generate all possible letter combinations for the grid
-pub fn generate_letter_combinations() -> Vec<CellPlural> {
-    let mut combos = Vec::with_capacity(26 * 26);
-    let letters = ('A'..='Z').collect::<Vec<_>>();
+use crate::models::cell::{CellPlural, CellSingular, FocusedCell, PendingClick, ScrollState};
+use crate::models::label::LabelOrder;
+use crate::models::layout::{self, GridLayout};
+use crate::models::selection::{Selection, SelectionAction, SelectionKey, SelectionRules};
+use crate::ui::{banner, RenderSettings};
+
+/// Generate letter combinations for a grid of `cols` x `rows` cells
+///
+/// All labels share the shortest length that covers the grid, so no label
+/// is a prefix of another.
+pub fn generate_letter_combinations(
+    cols: usize,
+    rows: usize,
+    letters: &[char],
+    order: LabelOrder,
+) -> Vec<CellPlural> {
+    let count = cols * rows;
+    let base = letters.len().max(1);
+
+    let mut label_len = 1;
+    let mut capacity = base;
+    while capacity < count {
+        label_len += 1;
+        capacity *= base;
+    }
 
-    for &a in &letters {
-        for &b in &letters {
-            combos.push(CellPlural::with_chars(a, b));
+    // Write each index in the alphabet's base, most significant letter first
+    let digits_of = |index: usize| {
+        let mut digits = vec![0; label_len];
+        let mut rest = index;
+        for slot in digits.iter_mut().rev() {
+            *slot = rest % base;
+            rest /= base;
+        }
+        digits
+    };
+    let to_cell = |digits: &[usize]| {
+        CellPlural::with_chars(&digits.iter().map(|&d| letters[d]).collect::<Vec<_>>())
+    };
+
+    match order {
+        LabelOrder::Sequential => (0..count).map(|index| to_cell(&digits_of(index))).collect(),
+        LabelOrder::Center => {
+            // Easiest labels first: earliest letters overall, then earliest letter
+            let mut labels: Vec<Vec<usize>> = (0..capacity).map(digits_of).collect();
+            labels.sort_by_key(|digits| (digits.iter().sum::<usize>(), digits.clone()));
+
+            // Cells closest to the center first
+            let center = ((cols as f32 - 1.0) / 2.0, (rows as f32 - 1.0) / 2.0);
+            let distance = |index: usize| {
+                let dx = (index % cols) as f32 - center.0;
+                let dy = (index / cols) as f32 - center.1;
+                dx * dx + dy * dy
+            };
+            let mut by_distance: Vec<usize> = (0..count).collect();
+            by_distance.sort_by(|&a, &b| distance(a).total_cmp(&distance(b)));
+
+            let mut combos = vec![CellPlural::new(); count];
+            for (index, digits) in by_distance.into_iter().zip(&labels) {
+                combos[index] = to_cell(digits);
+            }
+            combos
         }
     }
-
-    combos
 }
 
-/// Draw the main grid
-pub fn draw_grid(
+/// Draw the monitor picker shown before the grid on multi-monitor setups
+pub fn draw_monitor_picker(
     ctx: &Context,
     ui: &mut Ui,
-    cells: &[CellPlural],
+    monitors: &[Rect],
     focused_cell: &mut FocusedCell,
-    is_visible: &mut bool,
-    coordinates_margin: &Margin,
-    transparency: u8,
-    exit_key: Key,
+    settings: &RenderSettings,
 ) {
-    let available_size = ui.available_size_before_wrap();
+    let theme = &settings.theme;
+
+    // Monitors are numbered from 1, so only the first nine can be picked
+    for (index, rect) in monitors.iter().enumerate() {
+        let Some(digit) = char::from_digit(index as u32 + 1, 10) else {
+            break;
+        };
 
-    let desired_cell_size = 64.0;
+        // Handle key press for this monitor
+        if let Some(key) = keyboard::key_from_char(digit) {
+            if ctx.input(|i| i.key_pressed(key)) {
+                focused_cell.monitor = Some(index);
+            }
+        }
 
-    let cols = (available_size.x / desired_cell_size).floor().max(1.0) as usize;
-    let rows = (available_size.y / desired_cell_size).floor().max(1.0) as usize;
+        // Draw the monitor outline
+        ui.painter().rect(
+            *rect,
+            0.0,
+            Color32::TRANSPARENT,
+            Stroke::new(1.0, theme.grid_lines),
+            eframe::egui::StrokeKind::Inside,
+        );
 
-    let cell_width = available_size.x / cols as f32;
-    let cell_height = available_size.y / rows as f32;
+        // Draw the monitor number
+        ui.painter().text(
+            rect.center(),
+            Align2::CENTER_CENTER,
+            digit,
+            FontId::monospace(rect.height() * 0.4),
+            theme.label_text,
+        );
+    }
+}
 
-    let origin = ui.min_rect().min;
-    let transparent_color = Color32::from_rgba_unmultiplied(255, 235, 200, transparency);
+/// A grid to draw in a frame, with the options it is selected with
+pub struct GridFrame<'a> {
+    /// Area the grid fills
+    pub area: Rect,
+    /// Screen position of the overlay's top left corner
+    pub origin: (i32, i32),
+    /// Cell size, font scales and colors
+    pub settings: &'a RenderSettings,
+    /// Alphabets, keys and click options
+    pub config: &'a AppConfig,
+    /// Cell labels, regenerated when the grid changes size
+    pub cells: &'a mut Vec<CellPlural>,
+}
 
-    // Check for escape key to reset focused cell
-    if ctx.input(|i| i.key_pressed(exit_key)) {
-        focused_cell.reset();
-    }
-
-    // Check for letter keys to update focused cell
-    let letters = ('A'..='Z').collect::<Vec<_>>();
-    let mut enigo = mouse::create_enigo().unwrap_or_else(|e| {
-        eprintln!("Failed to create Enigo: {}", e);
-        panic!("Could not initialize mouse control");
-    });
-
-    // Handle key presses for the first level of selection
-    if !focused_cell.has_conclusion() {
-        for &a in &letters {
-            if let Some(key) = keyboard::key_from_char(a) {
-                if ctx.input(|i| i.key_released(key)) {
-                    if !focused_cell.has_first() {
-                        focused_cell.first = a;
-                    } else if !focused_cell.has_last() {
-                        focused_cell.last = a;
+/// Draw the main grid of `grid`
+///
+/// Keys are fed to the selection first, then its state is drawn.
+pub fn draw_grid(
+    ctx: &Context,
+    ui: &mut Ui,
+    pointer: &mut dyn PointerBackend,
+    grid: GridFrame<'_>,
+    mut selection: Selection<'_>,
+    is_visible: &mut bool,
+) {
+    let GridFrame {
+        area,
+        origin,
+        settings,
+        config,
+        cells,
+    } = grid;
+    let alphabets = &config.alphabets;
+    let recursive = config.recursive_micro_grid;
+    let layout = GridLayout::new(area, settings.cell_size, origin, ctx.pixels_per_point());
+
+    // Label every cell, growing labels when the grid outgrows them
+    if cells.len() != layout.cell_count() {
+        *cells = generate_letter_combinations(
+            layout.cols,
+            layout.rows,
+            &alphabets.hint,
+            alphabets.order,
+        );
+    }
+
+    let rules = SelectionRules {
+        layout: &layout,
+        cells,
+        alphabets,
+        click_action: config.click_action,
+        recursive,
+        confirm: config.confirm_click,
+    };
+
+    let keys = selection_keys(ctx, config.exit_key, config.drag_key, config.scroll_key);
+    for (key, modifiers) in keys {
+        match selection.handle(key, &modifiers, &rules) {
+            Some(SelectionAction::MoveTo(x, y)) => {
+                if let Err(e) = mouse::move_cursor_to(x, y, MouseAction::Move, pointer) {
+                    banner::report_error("Failed to move the cursor", e);
+                }
+            }
+            Some(SelectionAction::Perform { x, y, action, unit }) => {
+                match mouse::move_cursor_to(x, y, action, pointer) {
+                    Ok(()) if !selection.finish(unit) => {
+                        *is_visible = false;
+                        ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Visible(false));
+                        return;
                     }
+                    Ok(()) => {}
+                    Err(e) => banner::report_error("Failed to click", e),
                 }
             }
+            Some(SelectionAction::ReleaseButton) => {
+                if let Err(e) = mouse::release_button(pointer) {
+                    banner::report_error("Failed to release mouse button", e);
+                }
+            }
+            None => {}
         }
     }
 
-    // Draw the grid cells
-    let mut index = 0;
-    for row in 0..rows {
-        for col in 0..cols {
-            if index >= cells.len() {
-                return;
-            }
+    let theme = &settings.theme;
 
-            let first = cells[index].first;
-            let last = cells[index].last;
-            let combo = &cells[index].combo;
-
-            // Only draw cells that match the current selection
-            if !focused_cell.has_first() || focused_cell.first == first {
-                if !focused_cell.has_last() || focused_cell.last == last {
-                    let rect = Rect::from_min_size(
-                        origin + vec2(col as f32 * cell_width, row as f32 * cell_height),
-                        vec2(cell_width, cell_height),
-                    );
-
-                    ui.painter().rect(
-                        rect,
-                        0.0,
-                        transparent_color,
-                        Stroke::new(1.0, transparent_color),
-                        eframe::egui::StrokeKind::Outside,
-                    );
-
-                    if focused_cell.is_complete() {
-                        // Draw micro grid for the second level of selection
-                        draw_micro_grid(
-                            ctx,
-                            cell_width,
-                            cell_height,
-                            ui,
-                            rect,
-                            &mut enigo,
-                            coordinates_margin,
-                            !focused_cell.has_conclusion(),
-                            transparency,
-                            || {
-                                *is_visible = false;
-                                ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Visible(
-                                    false,
-                                ));
-                                focused_cell.reset();
-                            },
-                        );
-                    } else {
-                        // Draw the cell label
-                        ui.painter().text(
-                            rect.center(),
-                            Align2::CENTER_CENTER,
-                            combo,
-                            FontId::monospace(cell_height * 0.4),
-                            transparent_color,
-                        );
-                    }
-                }
-            }
+    // Show what the next selection does in drag or scroll mode
+    if let Some(label) = selection.mode_label() {
+        ui.painter().text(
+            area.min + vec2(layout.cell_width * 0.5, layout.cell_height * 0.5),
+            Align2::LEFT_TOP,
+            label,
+            FontId::monospace(layout.cell_height * settings.font_scale),
+            theme.label_text,
+        );
+    }
 
-            index += 1;
+    let focused_cell = &*selection.focused_cell;
+    if let Some(pending) = focused_cell.pending {
+        draw_confirm(ui, &layout, pending, settings);
+        return;
+    }
+
+    // Draw the cells that match the current selection
+    let complete = focused_cell.is_complete(rules.label_len());
+    for (index, cell) in cells.iter().enumerate().take(layout.cell_count()) {
+        if !focused_cell.matches(&cell.combo) {
+            continue;
+        }
+
+        let rect = layout.cell_rect(index);
+        ui.painter().rect(
+            rect,
+            0.0,
+            Color32::TRANSPARENT,
+            Stroke::new(1.0, theme.grid_lines),
+            eframe::egui::StrokeKind::Outside,
+        );
+
+        if complete {
+            // Draw micro grid for the second level of selection
+            draw_micro_grid(
+                ui,
+                rect,
+                settings,
+                recursive,
+                &alphabets.micro,
+                &focused_cell.zoom,
+            );
+        } else {
+            // Draw the cell label, highlighting the typed letters
+            let galley = theme.layout_label(
+                ui.painter(),
+                &cell.combo,
+                focused_cell.typed.chars().count(),
+                FontId::monospace(layout.cell_height * settings.font_scale),
+                theme.label_text,
+            );
+            let label_rect = Align2::CENTER_CENTER.anchor_size(rect.center(), galley.size());
+            ui.painter()
+                .galley(label_rect.min, galley, theme.label_text);
         }
     }
 }
 
-/// Draw the micro grid for the second level of selection
-pub fn draw_micro_grid<F>(
+/// Translate this frame's key events for the selection
+pub fn selection_keys(
     ctx: &Context,
-    parent_cell_width: f32,
-    parent_cell_height: f32,
+    exit_key: Key,
+    drag_key: Key,
+    scroll_key: Key,
+) -> Vec<(SelectionKey, Modifiers)> {
+    ctx.input(|i| {
+        i.events
+            .iter()
+            .filter_map(|event| {
+                let &Event::Key {
+                    key,
+                    pressed,
+                    modifiers,
+                    ..
+                } = event
+                else {
+                    return None;
+                };
+
+                let selection_key = if !pressed {
+                    SelectionKey::Released(keyboard::char_from_key(key)?)
+                } else if key == exit_key {
+                    SelectionKey::Exit
+                } else if key == drag_key {
+                    SelectionKey::Drag
+                } else if key == scroll_key {
+                    SelectionKey::Scroll
+                } else {
+                    match key {
+                        Key::Enter => SelectionKey::Enter,
+                        Key::Space => SelectionKey::Space,
+                        Key::Backspace => SelectionKey::Backspace,
+                        Key::ArrowLeft => SelectionKey::Arrow(-1, 0),
+                        Key::ArrowDown => SelectionKey::Arrow(0, 1),
+                        Key::ArrowUp => SelectionKey::Arrow(0, -1),
+                        Key::ArrowRight => SelectionKey::Arrow(1, 0),
+                        _ => SelectionKey::Pressed(keyboard::char_from_key(key)?),
+                    }
+                };
+                Some((selection_key, modifiers))
+            })
+            .collect()
+    })
+}
+
+/// Draw the micro grid of the selected cell at the current zoom level
+pub fn draw_micro_grid(
     ui: &mut Ui,
     parent_rect: Rect,
-    enigo: &mut Enigo,
-    margins: &Margin,
-    has_focus: bool,
-    transparency: u8,
-    mut on_keypress: F,
-) where
-    F: FnMut(),
-{
-    // Define the micro grid layout
-    let single_cell_values = "QWERASDFUOIPJKL;";
-    let cells: Vec<CellSingular> = single_cell_values
-        .chars()
-        .map(|c| CellSingular { unit: c })
-        .collect();
-
-    let length = cells.len();
-    let cols: usize = 4;
-    let rows: usize = 4;
-
-    let cell_width = parent_cell_width / cols as f32;
-    let cell_height = parent_cell_height / rows as f32;
-
-    let origin = parent_rect.min;
-    let transparent_color = Color32::from_rgba_unmultiplied(255, 235, 200, transparency);
-
-    let pixels_per_point = ui.ctx().pixels_per_point();
+    settings: &RenderSettings,
+    recursive: bool,
+    keys: &[char],
+    zoom: &[char],
+) {
+    let cells: Vec<CellSingular> = keys.iter().map(|&c| CellSingular { unit: c }).collect();
+
+    // Narrow the parent cell down to the current zoom level
+    let level_rect = GridLayout::zoom_rect(parent_rect, keys, zoom);
+
+    let theme = &settings.theme;
+
+    // Mark the point Enter would click
+    if recursive && !zoom.is_empty() {
+        let center = level_rect.center();
+        let stroke = Stroke::new(1.0, theme.grid_lines);
+        ui.painter().hline(parent_rect.x_range(), center.y, stroke);
+        ui.painter().vline(center.x, parent_rect.y_range(), stroke);
+    }
 
     // Draw each cell in the micro grid
     for (index, cell) in cells.iter().enumerate() {
-        if index >= length {
-            break;
+        let rect = layout::micro_cell_rect(level_rect, index);
+
+        ui.painter().rect(
+            rect,
+            0.0,
+            Color32::TRANSPARENT,
+            Stroke::new(1.0, theme.grid_lines),
+            eframe::egui::StrokeKind::Middle,
+        );
+
+        // Draw the cell label while it still fits
+        if rect.height() >= 8.0 {
+            ui.painter().text(
+                rect.center(),
+                Align2::CENTER_CENTER,
+                cell.unit,
+                FontId::monospace(rect.height() * settings.micro_font_scale),
+                theme.label_text,
+            );
         }
+    }
+}
 
-        let row = index / cols;
-        let col = index % cols;
+/// Draw the confirm stage for a placed pointer: a crosshair at the pointer
+fn draw_confirm(
+    ui: &mut Ui,
+    layout: &GridLayout,
+    pending: PendingClick,
+    settings: &RenderSettings,
+) {
+    let theme = &settings.theme;
 
-        let rect = Rect::from_min_size(
-            origin + vec2(col as f32 * cell_width, row as f32 * cell_height),
-            vec2(cell_width, cell_height),
-        );
+    let center = layout.to_window(pending.x, pending.y);
+    let stroke = Stroke::new(1.0, theme.grid_lines);
+    ui.painter().hline(layout.area.x_range(), center.y, stroke);
+    ui.painter().vline(center.x, layout.area.y_range(), stroke);
+    ui.painter().circle_stroke(center, 8.0, stroke);
+}
 
-        let pos = rect.center();
+/// Map scroll mode keys to scroll directions
+const SCROLL_KEYS: [(Key, Key, ScrollDirection); 4] = [
+    (Key::J, Key::ArrowDown, ScrollDirection::Down),
+    (Key::K, Key::ArrowUp, ScrollDirection::Up),
+    (Key::H, Key::ArrowLeft, ScrollDirection::Left),
+    (Key::L, Key::ArrowRight, ScrollDirection::Right),
+];
+
+/// Handle scroll mode after the cursor was placed over a cell
+///
+/// j/k/h/l and the arrow keys scroll under the cursor until `exit_key`
+/// closes the overlay.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_scroll_mode(
+    ctx: &Context,
+    ui: &mut Ui,
+    area: Rect,
+    pointer: &mut dyn PointerBackend,
+    scroll: &mut ScrollState,
+    scroller: &mut Scroller,
+    is_visible: &mut bool,
+    settings: &RenderSettings,
+    exit_key: Key,
+) {
+    let theme = &settings.theme;
 
-        // Calculate screen coordinates
-        let coordinates = (
-            ((pos.x + margins.left as f32) * pixels_per_point) as i32,
-            ((pos.y + margins.top as f32) * pixels_per_point) as i32,
-        );
+    if ctx.input(|i| i.key_pressed(exit_key)) {
+        *scroll = ScrollState::Idle;
+        *is_visible = false;
+        ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Visible(false));
+        return;
+    }
 
-        // Handle key press for this cell
-        if has_focus {
-            if let Some(key) = keyboard::key_from_char(cell.unit) {
-                if ctx.input(|i| i.key_pressed(key)) {
-                    if let Ok(()) = mouse::move_cursor_to(coordinates.0, coordinates.1, enigo) {
-                        on_keypress();
-                    }
+    for (key, arrow, direction) in SCROLL_KEYS {
+        if ctx.input(|i| i.key_pressed(key) || i.key_pressed(arrow)) {
+            if let Err(e) = scroller.scroll(direction, pointer) {
+                banner::report_error("Failed to scroll", e);
+            }
+        }
+    }
+
+    // Draw the scroll mode indicator
+    ui.painter().text(
+        area.left_top() + vec2(32.0, 32.0),
+        Align2::LEFT_TOP,
+        "SCROLL  j/k/h/l",
+        FontId::monospace(24.0),
+        theme.label_text,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Labels of a grid as strings
+    fn labels(cols: usize, rows: usize, letters: &str, order: LabelOrder) -> Vec<String> {
+        let letters: Vec<char> = letters.chars().collect();
+        generate_letter_combinations(cols, rows, &letters, order)
+            .into_iter()
+            .map(|cell| cell.combo)
+            .collect()
+    }
+
+    #[test]
+    fn labels_are_unique_and_equally_long() {
+        for order in [LabelOrder::Sequential, LabelOrder::Center] {
+            for (cols, rows, letters, label_len) in [
+                (3, 1, "ABC", 1),
+                (2, 2, "ABC", 2),
+                (3, 3, "ABC", 2),
+                (4, 3, "ABC", 3),
+                (30, 16, "ABCDEFGHIJKLMNOPQRSTUVWXYZ", 2),
+                (26, 26, "ABCDEFGHIJKLMNOPQRSTUVWXYZ", 2),
+                (26, 26, "ABCDEFGHIJKLMNOPQRSTUVWXY", 3),
+            ] {
+                let labels = labels(cols, rows, letters, order);
+                assert_eq!(labels.len(), cols * rows);
+
+                let unique: HashSet<&String> = labels.iter().collect();
+                assert_eq!(unique.len(), labels.len(), "{}x{} {:?}", cols, rows, order);
+
+                for label in &labels {
+                    assert_eq!(label.chars().count(), label_len, "{}x{}", cols, rows);
+                    assert!(label.chars().all(|c| letters.contains(c)));
                 }
             }
         }
+    }
 
-        // Draw the cell
-        ui.painter().rect(
-            rect,
-            0.0,
-            transparent_color,
-            Stroke::new(1.0, transparent_color),
-            eframe::egui::StrokeKind::Middle,
+    #[test]
+    fn sequential_labels_count_up() {
+        assert_eq!(
+            labels(2, 2, "ABC", LabelOrder::Sequential),
+            ["AA", "AB", "AC", "BA"]
+        );
+    }
+
+    #[test]
+    fn center_labels_on_an_odd_grid() {
+        // The middle cell gets the easiest label, the corners the hardest
+        assert_eq!(
+            labels(3, 3, "ABC", LabelOrder::Center),
+            ["CA", "AB", "BC", "BA", "AA", "AC", "CB", "BB", "CC"]
         );
+    }
 
-        // Draw the cell label
-        ui.painter().text(
-            rect.center(),
-            Align2::CENTER_CENTER,
-            cell.unit,
-            FontId::monospace(cell_height * 0.4),
-            transparent_color,
+    #[test]
+    fn center_labels_on_an_even_grid() {
+        // The four middle cells share the center, so they get the easiest labels
+        assert_eq!(
+            labels(4, 2, "ABCD", LabelOrder::Center),
+            ["BB", "AA", "AB", "CA", "AD", "BA", "AC", "BC"]
         );
     }
 }
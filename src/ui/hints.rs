@@ -0,0 +1,139 @@
+//! Hint labels drawn over accessible elements and top-level windows
+
+use eframe::egui::{Align2, Context, FontId, Pos2, Ui};
+
+use crate::config::AppConfig;
+use crate::error::Result;
+use crate::input::mouse::{self, MouseAction};
+use crate::input::pointer::PointerBackend;
+use crate::models::cell::CellPlural;
+use crate::models::hint::{HintTarget, WindowAction};
+use crate::models::label::LabelOrder;
+use crate::models::selection::{HintAction, Selection};
+use crate::system::x11;
+use crate::ui::{banner, grid, RenderSettings};
+
+/// Hint labels are drawn at half the size of grid labels
+const HINT_SCALE: f32 = 0.5;
+
+/// Hint targets to label in a frame, with the options they are picked with
+pub struct HintFrame<'a> {
+    /// Screen position of the overlay's top left corner
+    pub origin: (i32, i32),
+    /// Cell size, font scales and colors
+    pub settings: &'a RenderSettings,
+    /// Alphabets, keys and click options
+    pub config: &'a AppConfig,
+    /// Elements or windows to label; cleared to leave hint mode
+    pub targets: &'a mut Vec<HintTarget>,
+    /// Target labels, regenerated when the targets change
+    pub labels: &'a mut Vec<CellPlural>,
+}
+
+/// Draw a label over every hint target and click the one typed
+///
+/// Keys go through the selection as on the grid. Window targets are
+/// labelled at their center with their title, and are activated or clicked
+/// depending on `window_action`.
+pub fn draw_hints(
+    ctx: &Context,
+    ui: &mut Ui,
+    pointer: &mut dyn PointerBackend,
+    hints: HintFrame<'_>,
+    mut selection: Selection<'_>,
+    is_visible: &mut bool,
+) {
+    let HintFrame {
+        origin,
+        settings,
+        config,
+        targets,
+        labels,
+    } = hints;
+    let alphabets = &config.alphabets;
+
+    // Label the targets in reading order
+    if labels.len() != targets.len() {
+        *labels = grid::generate_letter_combinations(
+            targets.len(),
+            1,
+            &alphabets.hint,
+            LabelOrder::Sequential,
+        );
+    }
+
+    let keys = grid::selection_keys(ctx, config.exit_key, config.drag_key, config.scroll_key);
+    for (key, modifiers) in keys {
+        match selection.handle_hint(key, labels, alphabets) {
+            Some(HintAction::Pick(index)) => {
+                let action = MouseAction::from_modifiers(&modifiers, config.click_action);
+                let result = pick(&targets[index], action, config.window_action, pointer);
+                selection.focused_cell.reset();
+                match result {
+                    Ok(()) => {
+                        targets.clear();
+                        *is_visible = false;
+                        ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Visible(false));
+                    }
+                    Err(e) => banner::report_error("Failed to pick hint", e),
+                }
+                return;
+            }
+            Some(HintAction::Leave) => {
+                targets.clear();
+                return;
+            }
+            None => {}
+        }
+    }
+
+    // Draw the labels that match the typed letters
+    let pixels_per_point = ctx.pixels_per_point();
+    let font = FontId::monospace(settings.cell_size * settings.font_scale * HINT_SCALE);
+    let theme = &settings.theme;
+    let focused_cell = &*selection.focused_cell;
+    let typed = focused_cell.typed.chars().count();
+    for (target, label) in targets.iter().zip(labels.iter()) {
+        if !focused_cell.matches(&label.combo) {
+            continue;
+        }
+
+        // Elements are labelled at their corner, windows at their center
+        let (x, y, align, text) = if target.window.is_some() {
+            let (x, y) = target.center();
+            let text = if target.title.is_empty() {
+                label.combo.clone()
+            } else {
+                format!("{}  {}", label.combo, target.title)
+            };
+            (x, y, Align2::CENTER_CENTER, text)
+        } else {
+            (target.x, target.y, Align2::LEFT_TOP, label.combo.clone())
+        };
+        let pos = Pos2::new(
+            (x - origin.0) as f32 / pixels_per_point,
+            (y - origin.1) as f32 / pixels_per_point,
+        );
+
+        let galley = theme.layout_label(ui.painter(), &text, typed, font.clone(), theme.badge_text);
+        let rect = align.anchor_size(pos, galley.size());
+        ui.painter()
+            .rect_filled(rect.expand(2.0), 2.0, theme.badge_background);
+        ui.painter().galley(rect.min, galley, theme.badge_text);
+    }
+}
+
+/// Activate or click a picked target
+fn pick(
+    target: &HintTarget,
+    action: MouseAction,
+    window_action: WindowAction,
+    pointer: &mut dyn PointerBackend,
+) -> Result<()> {
+    if let (Some(window), WindowAction::Activate) = (target.window, window_action) {
+        return x11::activate_window(window);
+    }
+
+    let (x, y) = target.center();
+    mouse::move_cursor_to(x, y, action, pointer)
+}
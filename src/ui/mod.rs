@@ -1,21 +1,46 @@
 //! UI module
 
+pub mod banner;
 pub mod grid;
+pub mod hints;
+#[cfg(feature = "wayland-overlay")]
+pub mod raster;
+pub mod theme;
 
-use eframe::egui::{Color32, Frame, Margin};
+use eframe::egui::Frame;
 
-/// Create a transparent frame
-pub fn create_transparent_frame(margin: Margin, transparency: u8) -> Frame {
-    let transparent_color = Color32::from_rgba_unmultiplied(112, 66, 20, transparency);
+use crate::config::AppConfig;
+use crate::ui::theme::Theme;
 
-    Frame {
-        fill: transparent_color,
-        ..Frame::default()
+/// Rendering settings for the grid, taken from the configuration
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    /// Desired size of a grid cell in points
+    pub cell_size: f32,
+    /// Grid label size relative to the cell height
+    pub font_scale: f32,
+    /// Micro grid label size relative to the micro cell height
+    pub micro_font_scale: f32,
+    /// Overlay colors
+    pub theme: Theme,
+}
+
+impl RenderSettings {
+    /// Create rendering settings from the configuration
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            cell_size: config.cell_size,
+            font_scale: config.font_scale,
+            micro_font_scale: config.micro_font_scale,
+            theme: Theme::from_config(config),
+        }
     }
-    .outer_margin(margin)
 }
 
-/// Create a transparent color
-pub fn transparent_color(transparency: u8) -> Color32 {
-    Color32::from_rgba_unmultiplied(255, 235, 200, transparency)
+/// Create a frame tinting the covered area
+pub fn create_transparent_frame(theme: &Theme) -> Frame {
+    Frame {
+        fill: theme.tint,
+        ..Frame::default()
+    }
 }
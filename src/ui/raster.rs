@@ -0,0 +1,482 @@
+//! Software rendering of egui output, for overlays drawn without a GPU
+
+use std::collections::HashMap;
+
+use eframe::egui::epaint::textures::TexturesDelta;
+use eframe::egui::epaint::{ClippedPrimitive, ClippedShape, ImageData, Mesh, Primitive, Vertex};
+use eframe::egui::{Color32, Pos2, Rect, TextureId};
+
+/// Pixels to paint into, as premultiplied ARGB8888 in little-endian order,
+/// the layout of the `wl_shm` format of the same name
+pub struct Canvas<'a> {
+    pub pixels: &'a mut [u8],
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A texture uploaded by egui, with premultiplied pixels
+struct Texture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color32>,
+}
+
+impl Texture {
+    /// Copy the pixels of an egui image
+    fn from_image(image: &ImageData) -> Self {
+        let pixels = match image {
+            ImageData::Color(image) => image.pixels.clone(),
+            ImageData::Font(image) => image.srgba_pixels(None).collect(),
+        };
+        let [width, height] = image.size();
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Copy `patch` into the texture with its top left corner at `pos`
+    fn update(&mut self, [x, y]: [usize; 2], patch: Texture) {
+        for row in 0..patch.height.min(self.height.saturating_sub(y)) {
+            let width = patch.width.min(self.width.saturating_sub(x));
+            let start = (y + row) * self.width + x;
+            self.pixels[start..start + width]
+                .copy_from_slice(&patch.pixels[row * patch.width..row * patch.width + width]);
+        }
+    }
+
+    /// Sample the texture at `uv` with bilinear filtering, clamped at the
+    /// edges
+    fn sample(&self, uv: Pos2) -> [f32; 4] {
+        if self.pixels.is_empty() {
+            return [255.0; 4];
+        }
+
+        let x = (uv.x * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (uv.y * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+        let texel = |x: usize, y: usize| self.pixels[y * self.width + x].to_array();
+        let (top_left, top_right) = (texel(x0, y0), texel(x1, y0));
+        let (bottom_left, bottom_right) = (texel(x0, y1), texel(x1, y1));
+        std::array::from_fn(|i| {
+            let top = top_left[i] as f32 * (1.0 - fx) + top_right[i] as f32 * fx;
+            let bottom = bottom_left[i] as f32 * (1.0 - fx) + bottom_right[i] as f32 * fx;
+            top * (1.0 - fy) + bottom * fy
+        })
+    }
+}
+
+/// Rasterizer for tessellated egui shapes, keeping the textures egui uploads
+#[derive(Default)]
+pub struct Rasterizer {
+    textures: HashMap<TextureId, Texture>,
+}
+
+impl Rasterizer {
+    /// Create a rasterizer without textures
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Upload the new and changed textures of a frame, before painting it
+    pub fn set_textures(&mut self, delta: &TexturesDelta) {
+        for (id, image_delta) in &delta.set {
+            let image = Texture::from_image(&image_delta.image);
+            match (image_delta.pos, self.textures.get_mut(id)) {
+                (Some(pos), Some(texture)) => texture.update(pos, image),
+                _ => {
+                    self.textures.insert(*id, image);
+                }
+            }
+        }
+    }
+
+    /// Drop the textures egui freed, after painting the frame
+    pub fn free_textures(&mut self, delta: &TexturesDelta) {
+        for id in &delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    /// Clear `area` of the canvas, as `[x0, y0, x1, y1]`, then paint the
+    /// primitives of a frame inside it
+    pub fn paint(
+        &self,
+        primitives: &[ClippedPrimitive],
+        pixels_per_point: f32,
+        canvas: &mut Canvas<'_>,
+        area: [usize; 4],
+    ) {
+        let [x0, y0, x1, y1] = area;
+        for y in y0..y1 {
+            let row = y * canvas.width;
+            canvas.pixels[(row + x0) * 4..(row + x1) * 4].fill(0);
+        }
+
+        for primitive in primitives {
+            let Primitive::Mesh(mesh) = &primitive.primitive else {
+                continue;
+            };
+            let [clip_x0, clip_y0, clip_x1, clip_y1] =
+                pixel_rect(primitive.clip_rect, pixels_per_point, canvas);
+            let clip = [
+                clip_x0.max(x0),
+                clip_y0.max(y0),
+                clip_x1.min(x1),
+                clip_y1.min(y1),
+            ];
+            self.paint_mesh(mesh, clip, pixels_per_point, canvas);
+        }
+    }
+
+    /// Paint the triangles of a mesh, inside `clip`
+    fn paint_mesh(
+        &self,
+        mesh: &Mesh,
+        clip: [usize; 4],
+        pixels_per_point: f32,
+        canvas: &mut Canvas<'_>,
+    ) {
+        let texture = self.textures.get(&mesh.texture_id);
+        for triangle in mesh.indices.chunks_exact(3) {
+            let vertices = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+            if vertices.iter().all(|v| v.color == Color32::TRANSPARENT) {
+                continue;
+            }
+            fill_triangle(vertices, texture, clip, pixels_per_point, canvas);
+        }
+    }
+}
+
+/// Get the pixels a clip rectangle covers, as `[x0, y0, x1, y1]` inside the
+/// canvas
+fn pixel_rect(rect: Rect, pixels_per_point: f32, canvas: &Canvas<'_>) -> [usize; 4] {
+    let x = |points: f32| ((points * pixels_per_point).round().max(0.0) as usize).min(canvas.width);
+    let y =
+        |points: f32| ((points * pixels_per_point).round().max(0.0) as usize).min(canvas.height);
+    [x(rect.min.x), y(rect.min.y), x(rect.max.x), y(rect.max.y)]
+}
+
+/// Get the pixels that differ between two frames of shapes, as
+/// `[x0, y0, x1, y1]` inside a `width` x `height` canvas, or `None` when the
+/// frames look the same
+///
+/// Shapes are compared in paint order, and both the old and the new bounds
+/// of a changed shape are damaged.
+pub fn damage(
+    previous: &[ClippedShape],
+    shapes: &[ClippedShape],
+    pixels_per_point: f32,
+    width: usize,
+    height: usize,
+) -> Option<[usize; 4]> {
+    let mut damage = None;
+    for index in 0..previous.len().max(shapes.len()) {
+        let (old, new) = (previous.get(index), shapes.get(index));
+        if old == new {
+            continue;
+        }
+
+        for shape in [old, new].into_iter().flatten() {
+            let bounds = shape
+                .shape
+                .visual_bounding_rect()
+                .intersect(shape.clip_rect);
+            if bounds.is_positive() {
+                let covered = covering_rect(bounds, pixels_per_point, width, height);
+                damage = union(damage, covered);
+            }
+        }
+    }
+    damage
+}
+
+/// Get the smallest rectangle holding two `[x0, y0, x1, y1]` rectangles
+pub fn union(a: Option<[usize; 4]>, b: Option<[usize; 4]>) -> Option<[usize; 4]> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some([
+            a[0].min(b[0]),
+            a[1].min(b[1]),
+            a[2].max(b[2]),
+            a[3].max(b[3]),
+        ]),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Get the pixels a shape's bounds touch, with a pixel to spare for
+/// anti-aliasing, or `None` when they are outside the canvas
+fn covering_rect(
+    rect: Rect,
+    pixels_per_point: f32,
+    width: usize,
+    height: usize,
+) -> Option<[usize; 4]> {
+    let low = |points: f32| ((points * pixels_per_point).floor().max(1.0) as usize) - 1;
+    let high = |points: f32| (points * pixels_per_point).ceil().max(0.0) as usize + 1;
+    let covered = [
+        low(rect.min.x),
+        low(rect.min.y),
+        high(rect.max.x).min(width),
+        high(rect.max.y).min(height),
+    ];
+    (covered[0] < covered[2] && covered[1] < covered[3]).then_some(covered)
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `p`, positive when `p`
+/// is on the inner side of the edge from `a` to `b`
+fn edge(a: [f64; 2], b: [f64; 2], p: [f64; 2]) -> f64 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+/// Check if a pixel center at edge distance `w` belongs to the triangle
+///
+/// Centers exactly on an edge only belong to the triangle below or right of
+/// it, so triangles sharing an edge never blend a pixel twice.
+fn covers(w: f64, a: [f64; 2], b: [f64; 2]) -> bool {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    w > 0.0 || (w == 0.0 && (dy < 0.0 || (dy == 0.0 && dx > 0.0)))
+}
+
+/// Blend a triangle into the canvas, interpolating its vertex colors and
+/// texture coordinates
+fn fill_triangle(
+    mut vertices: [&Vertex; 3],
+    texture: Option<&Texture>,
+    clip: [usize; 4],
+    pixels_per_point: f32,
+    canvas: &mut Canvas<'_>,
+) {
+    let position = |v: &Vertex| {
+        [
+            (v.pos.x * pixels_per_point) as f64,
+            (v.pos.y * pixels_per_point) as f64,
+        ]
+    };
+    let mut area = edge(
+        position(vertices[0]),
+        position(vertices[1]),
+        position(vertices[2]),
+    );
+    if area == 0.0 {
+        return;
+    }
+    if area < 0.0 {
+        vertices.swap(1, 2);
+        area = -area;
+    }
+    let [a, b, c] = vertices.map(position);
+
+    // Shapes are mostly flat colored, which need no interpolation
+    let shade = |weights: [f64; 3]| {
+        let uv = vertices
+            .iter()
+            .zip(weights)
+            .fold(Pos2::ZERO, |uv, (v, w)| uv + v.uv.to_vec2() * w as f32);
+        let texel = texture.map_or([255.0; 4], |texture| texture.sample(uv));
+        std::array::from_fn(|i| {
+            let color: f32 = vertices
+                .iter()
+                .zip(weights)
+                .map(|(v, w)| v.color[i] as f32 * w as f32)
+                .sum();
+            color * texel[i] / 255.0
+        })
+    };
+    let flat = vertices
+        .iter()
+        .all(|v| v.color == vertices[0].color && v.uv == vertices[0].uv);
+    let flat_color: [f32; 4] = shade([1.0, 0.0, 0.0]);
+
+    let [clip_x0, clip_y0, clip_x1, clip_y1] = clip;
+    let min = |i: usize| a[i].min(b[i]).min(c[i]).floor().max(0.0) as usize;
+    let max = |i: usize| a[i].max(b[i]).max(c[i]).ceil().max(0.0) as usize;
+    for y in min(1).max(clip_y0)..max(1).min(clip_y1) {
+        for x in min(0).max(clip_x0)..max(0).min(clip_x1) {
+            let p = [x as f64 + 0.5, y as f64 + 0.5];
+            let (wa, wb, wc) = (edge(b, c, p), edge(c, a, p), edge(a, b, p));
+            if !(covers(wa, b, c) && covers(wb, c, a) && covers(wc, a, b)) {
+                continue;
+            }
+
+            let color = if flat {
+                flat_color
+            } else {
+                shade([wa / area, wb / area, wc / area])
+            };
+            let offset = (y * canvas.width + x) * 4;
+            blend(&mut canvas.pixels[offset..offset + 4], color);
+        }
+    }
+}
+
+/// Blend a premultiplied RGBA color over a premultiplied BGRA pixel
+fn blend(pixel: &mut [u8], [r, g, b, a]: [f32; 4]) {
+    let keep = 1.0 - a / 255.0;
+    for (channel, color) in pixel.iter_mut().zip([b, g, r, a]) {
+        *channel = (color + *channel as f32 * keep).round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui::epaint::{ColorImage, ImageDelta};
+    use eframe::egui::{pos2, TextureOptions};
+
+    /// Paint a rectangle with a white texture, the way egui fills shapes
+    fn paint_rect(rect: Rect, clip: Rect, color: Color32, size: usize) -> Vec<u8> {
+        let mut rasterizer = Rasterizer::new();
+        let mut delta = TexturesDelta::default();
+        delta.set.push((
+            TextureId::default(),
+            ImageDelta::full(
+                ColorImage::new([1, 1], Color32::WHITE),
+                TextureOptions::LINEAR,
+            ),
+        ));
+        rasterizer.set_textures(&delta);
+
+        let mut mesh = Mesh::default();
+        mesh.add_colored_rect(rect, color);
+        let primitives = [ClippedPrimitive {
+            clip_rect: clip,
+            primitive: Primitive::Mesh(mesh),
+        }];
+
+        let mut pixels = vec![0; size * size * 4];
+        let mut canvas = Canvas {
+            pixels: &mut pixels,
+            width: size,
+            height: size,
+        };
+        rasterizer.paint(&primitives, 1.0, &mut canvas, [0, 0, size, size]);
+        pixels
+    }
+
+    #[test]
+    fn translucent_fills_blend_each_pixel_once() {
+        let screen = Rect::from_min_max(pos2(0.0, 0.0), pos2(8.0, 8.0));
+        let tint = Color32::from_rgba_premultiplied(0, 0, 64, 64);
+        let pixels = paint_rect(screen, screen, tint, 8);
+
+        for pixel in pixels.chunks_exact(4) {
+            assert_eq!(pixel, [64, 0, 0, 64]);
+        }
+    }
+
+    #[test]
+    fn text_is_painted_from_the_font_texture() {
+        let ctx = eframe::egui::Context::default();
+        let output = ctx.run(Default::default(), |ctx| {
+            eframe::egui::CentralPanel::default().show(ctx, |ui| ui.label("Kmouse"));
+        });
+        let primitives = ctx.tessellate(output.shapes, output.pixels_per_point);
+
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_textures(&output.textures_delta);
+        let mut pixels = vec![0; 64 * 32 * 4];
+        let mut canvas = Canvas {
+            pixels: &mut pixels,
+            width: 64,
+            height: 32,
+        };
+        rasterizer.paint(
+            &primitives,
+            output.pixels_per_point,
+            &mut canvas,
+            [0, 0, 64, 32],
+        );
+
+        // The panel is opaque and the label a different color than it
+        let background = &pixels[..4];
+        assert_eq!(background[3], 255);
+        assert!(pixels.chunks_exact(4).any(|pixel| pixel != background));
+    }
+
+    #[test]
+    fn clip_rect_limits_painting() {
+        let screen = Rect::from_min_max(pos2(0.0, 0.0), pos2(8.0, 8.0));
+        let clip = Rect::from_min_max(pos2(0.0, 0.0), pos2(4.0, 8.0));
+        let pixels = paint_rect(screen, clip, Color32::WHITE, 8);
+
+        for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+            let expected = if i % 8 < 4 { [255; 4] } else { [0; 4] };
+            assert_eq!(pixel, expected, "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn painting_an_area_keeps_the_rest() {
+        let screen = Rect::from_min_max(pos2(0.0, 0.0), pos2(8.0, 8.0));
+        let mut mesh = Mesh::default();
+        mesh.add_colored_rect(screen, Color32::WHITE);
+        let primitives = [ClippedPrimitive {
+            clip_rect: screen,
+            primitive: Primitive::Mesh(mesh),
+        }];
+
+        let mut pixels = vec![7; 8 * 8 * 4];
+        let mut canvas = Canvas {
+            pixels: &mut pixels,
+            width: 8,
+            height: 8,
+        };
+        Rasterizer::new().paint(&primitives, 1.0, &mut canvas, [2, 4, 6, 8]);
+
+        for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+            let (x, y) = (i % 8, i / 8);
+            let inside = (2..6).contains(&x) && (4..8).contains(&y);
+            let expected = if inside { [255; 4] } else { [7; 4] };
+            assert_eq!(pixel, expected, "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn damage_covers_changed_shapes_only() {
+        let screen = Rect::from_min_max(pos2(0.0, 0.0), pos2(64.0, 64.0));
+        let rect = |min: f32, max: f32| ClippedShape {
+            clip_rect: screen,
+            shape: eframe::egui::Shape::rect_filled(
+                Rect::from_min_max(pos2(min, min), pos2(max, max)),
+                0.0,
+                Color32::WHITE,
+            ),
+        };
+        let previous = [rect(0.0, 64.0), rect(8.0, 12.0)];
+
+        assert_eq!(damage(&previous, &previous, 1.0, 64, 64), None);
+
+        // The label moved: both its old and its new place change
+        let shapes = [rect(0.0, 64.0), rect(20.0, 24.0)];
+        assert_eq!(
+            damage(&previous, &shapes, 1.0, 64, 64),
+            Some([7, 7, 25, 25])
+        );
+        assert_eq!(
+            damage(&previous, &shapes, 2.0, 128, 128),
+            Some([15, 15, 49, 49])
+        );
+
+        // A label went away
+        assert_eq!(
+            damage(&previous, &previous[..1], 1.0, 64, 64),
+            Some([7, 7, 13, 13])
+        );
+    }
+
+    #[test]
+    fn union_of_pixel_rects() {
+        assert_eq!(union(None, None), None);
+        assert_eq!(union(Some([1, 2, 3, 4]), None), Some([1, 2, 3, 4]));
+        assert_eq!(
+            union(Some([1, 2, 3, 4]), Some([0, 3, 5, 4])),
+            Some([0, 2, 5, 4])
+        );
+    }
+}
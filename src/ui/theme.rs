@@ -0,0 +1,201 @@
+//! Overlay colors and their presets
+
+use std::sync::Arc;
+
+use eframe::egui::text::{LayoutJob, TextFormat};
+use eframe::egui::{Color32, FontId, Galley, Painter};
+
+use crate::config::AppConfig;
+
+/// A built-in set of overlay colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThemePreset {
+    /// Brown tint at the `ui_transparency` alpha, with cream lines and labels
+    #[default]
+    Classic,
+    /// Opaque white labels and yellow lines on a dark tint
+    HighContrast,
+    Dark,
+    Light,
+    /// Blue and orange, which stay apart with red-green color blindness
+    DeuteranopiaSafe,
+}
+
+impl ThemePreset {
+    /// Get a preset from its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(Self::Classic),
+            "high-contrast" => Some(Self::HighContrast),
+            "dark" => Some(Self::Dark),
+            "light" => Some(Self::Light),
+            "deuteranopia-safe" => Some(Self::DeuteranopiaSafe),
+            _ => None,
+        }
+    }
+}
+
+/// Colors of the overlay, each with its own alpha
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// Tint over the covered area
+    pub tint: Color32,
+    /// Cell outlines and crosshairs
+    pub grid_lines: Color32,
+    /// Label text drawn straight on the overlay
+    pub label_text: Color32,
+    /// Background behind hint labels
+    pub badge_background: Color32,
+    /// Hint label text, drawn on the badge background
+    pub badge_text: Color32,
+    /// Already typed part of the labels
+    pub matched_prefix: Color32,
+}
+
+impl Theme {
+    /// Get the colors of a preset; `transparency` only applies to the tint
+    /// of the classic preset, so labels stay opaque
+    pub fn preset(preset: ThemePreset, transparency: u8) -> Self {
+        let rgba = Color32::from_rgba_unmultiplied;
+        match preset {
+            ThemePreset::Classic => Self {
+                tint: rgba(112, 66, 20, transparency),
+                grid_lines: rgba(255, 235, 200, 140),
+                label_text: rgba(255, 235, 200, 255),
+                badge_background: rgba(112, 66, 20, 220),
+                badge_text: rgba(255, 235, 200, 255),
+                matched_prefix: rgba(255, 170, 60, 255),
+            },
+            ThemePreset::HighContrast => Self {
+                tint: rgba(0, 0, 0, 90),
+                grid_lines: rgba(255, 255, 0, 255),
+                label_text: rgba(255, 255, 255, 255),
+                badge_background: rgba(0, 0, 0, 255),
+                badge_text: rgba(255, 255, 255, 255),
+                matched_prefix: rgba(255, 255, 0, 255),
+            },
+            ThemePreset::Dark => Self {
+                tint: rgba(16, 16, 24, 120),
+                grid_lines: rgba(200, 200, 210, 140),
+                label_text: rgba(235, 235, 240, 255),
+                badge_background: rgba(30, 30, 40, 230),
+                badge_text: rgba(235, 235, 240, 255),
+                matched_prefix: rgba(120, 180, 255, 255),
+            },
+            ThemePreset::Light => Self {
+                tint: rgba(250, 250, 245, 60),
+                grid_lines: rgba(40, 40, 40, 130),
+                label_text: rgba(20, 20, 20, 255),
+                badge_background: rgba(255, 255, 255, 235),
+                badge_text: rgba(20, 20, 20, 255),
+                matched_prefix: rgba(200, 60, 0, 255),
+            },
+            ThemePreset::DeuteranopiaSafe => Self {
+                tint: rgba(0, 0, 0, 60),
+                grid_lines: rgba(0, 114, 178, 220),
+                label_text: rgba(255, 255, 255, 255),
+                badge_background: rgba(0, 45, 80, 230),
+                badge_text: rgba(255, 255, 255, 255),
+                matched_prefix: rgba(230, 159, 0, 255),
+            },
+        }
+    }
+
+    /// Get the theme from the configuration: a preset with single colors
+    /// overridden
+    pub fn from_config(config: &AppConfig) -> Self {
+        let preset = Self::preset(config.theme, config.ui_transparency);
+        Self {
+            tint: config.tint_color.unwrap_or(preset.tint),
+            grid_lines: config.grid_line_color.unwrap_or(preset.grid_lines),
+            label_text: config.label_color.unwrap_or(preset.label_text),
+            badge_background: config.badge_color.unwrap_or(preset.badge_background),
+            badge_text: config.badge_text_color.unwrap_or(preset.badge_text),
+            matched_prefix: config.prefix_color.unwrap_or(preset.matched_prefix),
+        }
+    }
+
+    /// Lay out a label in `color`, with its first `typed` characters in the
+    /// matched prefix color
+    pub fn layout_label(
+        &self,
+        painter: &Painter,
+        text: &str,
+        typed: usize,
+        font: FontId,
+        color: Color32,
+    ) -> Arc<Galley> {
+        let split = text
+            .char_indices()
+            .nth(typed)
+            .map_or(text.len(), |(index, _)| index);
+
+        let mut job = LayoutJob::default();
+        job.append(
+            &text[..split],
+            0.0,
+            TextFormat::simple(font.clone(), self.matched_prefix),
+        );
+        job.append(&text[split..], 0.0, TextFormat::simple(font, color));
+        painter.layout_job(job)
+    }
+}
+
+/// Parse a color written as `#RRGGBB` or `#RRGGBBAA`
+pub fn parse_color(text: &str) -> Option<Color32> {
+    let hex = text.strip_prefix('#')?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Some(Color32::from_rgba_unmultiplied(
+        channel(0)?,
+        channel(2)?,
+        channel(4)?,
+        alpha,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transparency_only_applies_to_the_classic_tint() {
+        let theme = Theme::preset(ThemePreset::Classic, 10);
+        assert_eq!(theme.tint.a(), 10);
+        assert_eq!(theme.grid_lines.a(), 140);
+    }
+
+    #[test]
+    fn labels_are_opaque_in_every_preset() {
+        for preset in [
+            ThemePreset::Classic,
+            ThemePreset::HighContrast,
+            ThemePreset::Dark,
+            ThemePreset::Light,
+            ThemePreset::DeuteranopiaSafe,
+        ] {
+            for transparency in [0, 10, 255] {
+                let theme = Theme::preset(preset, transparency);
+                assert_eq!(theme.label_text.a(), 255, "{:?}", preset);
+                assert_eq!(theme.badge_text.a(), 255, "{:?}", preset);
+                assert_eq!(theme.matched_prefix.a(), 255, "{:?}", preset);
+            }
+        }
+    }
+
+    #[test]
+    fn colors_parse_with_optional_alpha() {
+        assert_eq!(parse_color("#ff8000"), Some(Color32::from_rgb(255, 128, 0)));
+        assert_eq!(
+            parse_color("#FF800080"),
+            Some(Color32::from_rgba_unmultiplied(255, 128, 0, 128))
+        );
+        for invalid in ["ff8000", "#ff80", "#ff8000f", "#gg8000", "#ff80é0"] {
+            assert_eq!(parse_color(invalid), None, "{}", invalid);
+        }
+    }
+}